@@ -0,0 +1,5 @@
+// Installable PWAs need a service worker with a fetch handler before the share target shows up in
+// the share sheet. We don't cache anything, every request goes straight to the network.
+self.addEventListener('install', () => self.skipWaiting());
+self.addEventListener('activate', (event) => event.waitUntil(self.clients.claim()));
+self.addEventListener('fetch', () => {});
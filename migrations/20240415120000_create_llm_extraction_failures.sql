@@ -0,0 +1,7 @@
+create table llm_extraction_failures
+(
+    id                 serial primary key,
+    instagram_video_id integer                  not null unique references instagram_video (id),
+    reason             text                     not null,
+    failed_at          timestamp with time zone not null default now()
+);
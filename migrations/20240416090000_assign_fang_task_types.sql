@@ -0,0 +1,12 @@
+-- Jobs are now routed to per-type worker pools, move any queued tasks off the default type
+update fang_tasks
+set task_type = 'fetch_reel'
+where metadata ->> 'type' = 'FetchReelJob';
+
+update fang_tasks
+set task_type = 'extract_transcript'
+where metadata ->> 'type' = 'ExtractTranscriptJob';
+
+update fang_tasks
+set task_type = 'llm_extract_details'
+where metadata ->> 'type' = 'LLmExtractDetailsJob';
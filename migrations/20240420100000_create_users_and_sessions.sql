@@ -0,0 +1,26 @@
+create table users
+(
+    id            serial primary key,
+    username      text                     not null unique,
+    password_hash text                     not null,
+    created_at    timestamp with time zone not null default now()
+);
+
+create table sessions
+(
+    token_hash text primary key,
+    user_id    integer                  not null references users (id) on delete cascade,
+    created_at timestamp with time zone not null default now(),
+    expires_at timestamp with time zone not null
+);
+
+create index sessions_user_id_index on sessions (user_id);
+
+-- Videos stay unique by instagram_id, the owner is whoever first imported them
+alter table instagram_video
+    add column owner_id integer references users (id);
+
+alter table recipes
+    add column owner_id integer references users (id);
+
+create index recipes_owner_id_index on recipes (owner_id);
@@ -0,0 +1,56 @@
+create type household_role as enum ('owner', 'editor', 'viewer');
+
+create table households
+(
+    id         serial primary key,
+    name       text                     not null,
+    created_at timestamp with time zone not null default now()
+);
+
+-- A user belongs to exactly one household at a time
+create table household_members
+(
+    household_id integer                  not null references households (id) on delete cascade,
+    user_id      integer                  not null unique references users (id) on delete cascade,
+    role         household_role           not null,
+    created_at   timestamp with time zone not null default now(),
+    primary key (household_id, user_id)
+);
+
+create table household_invitations
+(
+    token_hash   text primary key,
+    household_id integer                  not null references households (id) on delete cascade,
+    role         household_role           not null,
+    created_by   integer                  not null references users (id) on delete cascade,
+    created_at   timestamp with time zone not null default now(),
+    expires_at   timestamp with time zone not null,
+    accepted_by  integer references users (id) on delete set null,
+    accepted_at  timestamp with time zone
+);
+
+alter table recipes
+    add column household_id integer references households (id);
+
+create index recipes_household_id_index on recipes (household_id);
+
+-- Every existing user starts out owning a household of their own
+do
+$$
+    declare
+        u                record;
+        new_household_id integer;
+    begin
+        for u in select id, username from users order by id
+            loop
+                insert into households (name)
+                values (u.username || '''s household')
+                returning id into new_household_id;
+
+                insert into household_members (household_id, user_id, role)
+                values (new_household_id, u.id, 'owner');
+
+                update recipes set household_id = new_household_id where owner_id = u.id;
+            end loop;
+    end
+$$;
@@ -0,0 +1,13 @@
+create table api_tokens
+(
+    id           serial primary key,
+    user_id      integer                  not null references users (id) on delete cascade,
+    name         text                     not null,
+    token_hash   text                     not null unique,
+    scopes       text[]                   not null,
+    created_at   timestamp with time zone not null default now(),
+    last_used_at timestamp with time zone,
+    expires_at   timestamp with time zone
+);
+
+create index api_tokens_user_id_index on api_tokens (user_id);
@@ -0,0 +1,27 @@
+create type import_item_status as enum ('queued', 'duplicate', 'invalid');
+
+create table import_batches
+(
+    id           serial primary key,
+    household_id integer                  not null references households (id) on delete cascade,
+    created_by   integer                  not null references users (id) on delete cascade,
+    -- Where the links came from, "pasted" or the uploaded file's name
+    source       text                     not null,
+    created_at   timestamp with time zone not null default now()
+);
+
+create index import_batches_household_id_index on import_batches (household_id);
+
+create table import_items
+(
+    id           serial primary key,
+    batch_id     integer                  not null references import_batches (id) on delete cascade,
+    url          text                     not null,
+    reel_id      text,
+    status       import_item_status       not null,
+    -- The queued `fetch_reel` task, fang deletes it once it has finished or failed for good
+    task_id      uuid,
+    scheduled_at timestamp with time zone
+);
+
+create index import_items_batch_id_index on import_items (batch_id);
@@ -0,0 +1,24 @@
+create table collections
+(
+    id           serial primary key,
+    household_id integer                  not null references households (id) on delete cascade,
+    name         text                     not null,
+    created_at   timestamp with time zone not null default now(),
+    unique (household_id, name)
+);
+
+create table collection_recipes
+(
+    collection_id integer                  not null references collections (id) on delete cascade,
+    recipe_id     integer                  not null references recipes (id) on delete cascade,
+    added_at      timestamp with time zone not null default now(),
+    primary key (collection_id, recipe_id)
+);
+
+-- Collections an imported link was saved to on Instagram, applied once its recipes exist
+create table import_item_collections
+(
+    import_item_id integer not null references import_items (id) on delete cascade,
+    collection_id  integer not null references collections (id) on delete cascade,
+    primary key (import_item_id, collection_id)
+);
@@ -0,0 +1,2 @@
+alter table instagram_video
+    add column on_screen_text text;
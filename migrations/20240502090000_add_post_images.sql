@@ -0,0 +1,4 @@
+-- Image file names for carousel posts, stored under the reel directory in a folder named after
+-- the post. Null for videos.
+alter table instagram_video
+    add column images text[];
@@ -0,0 +1,5 @@
+-- How much a recipe makes and how long it takes, as given in the reel. Null when it doesn't say.
+alter table recipes
+    add column servings     text,
+    add column prep_minutes integer,
+    add column cook_minutes integer;
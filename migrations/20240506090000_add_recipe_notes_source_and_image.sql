@@ -0,0 +1,7 @@
+-- Filled in for recipes imported from other recipe managers, which keep notes, the page the
+-- recipe came from and a photo. The image is a file name in the reel directory's
+-- `recipe-images` folder.
+alter table recipes
+    add column notes      text,
+    add column source_url text,
+    add column image      text;
@@ -0,0 +1,6 @@
+-- The household whose extraction failed, so each household only sees its own failures. Failures
+-- recorded before this have no household and aren't shown.
+alter table llm_extraction_failures
+    add column household_id integer references households (id) on delete cascade;
+
+create index llm_extraction_failures_household_id_index on llm_extraction_failures (household_id);
@@ -0,0 +1,13 @@
+-- Each household keeps its own failure for a video, so one household's failure or success
+-- doesn't hide or clear another's. Failures without a household were never shown.
+delete
+from llm_extraction_failures
+where household_id is null;
+
+alter table llm_extraction_failures
+    alter column household_id set not null,
+    drop constraint llm_extraction_failures_instagram_video_id_key,
+    add unique (household_id, instagram_video_id);
+
+-- Covered by the unique index, which starts with the household
+drop index llm_extraction_failures_household_id_index;
@@ -0,0 +1,211 @@
+//! Links each instruction step to the moment in the video where it's described, by matching the
+//! step's words against the transcript segments.
+//!
+//! Steps are assumed to be described in the same order they're written, so the segments picked
+//! never go backwards. A step with no segment sharing enough of its words is left unlinked.
+
+use crate::jobs::extract_transcript::Segment;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Share of a step's words that have to turn up in a segment for it to count as a match.
+const MIN_SCORE: f64 = 0.3;
+
+/// Too common in recipes to say anything about which step is which.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "for", "from", "get", "go", "in", "into",
+    "is", "it", "just", "of", "on", "or", "so", "some", "that", "the", "then", "this", "to",
+    "until", "up", "we", "with", "you", "your",
+];
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| word.len() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        // Crude plurals, so "onions" in the step matches "onion" in the video
+        .map(|word| match word.strip_suffix('s') {
+            Some(stem) if stem.len() > 2 => stem.to_string(),
+            _ => word,
+        })
+        .collect()
+}
+
+/// How much of `step` is covered by `segment`. A step is often spoken across two segments, so
+/// words only found in the one after count for half, enough to link it without pulling the
+/// step back before where it starts.
+fn score(step: &HashSet<String>, segment: &HashSet<String>, next: Option<&HashSet<String>>) -> f64 {
+    if step.is_empty() {
+        return 0.0;
+    }
+
+    let here = step.iter().filter(|word| segment.contains(*word)).count();
+    let after = step
+        .iter()
+        .filter(|word| !segment.contains(*word) && next.is_some_and(|next| next.contains(*word)))
+        .count();
+
+    (here as f64 + after as f64 / 2.0) / step.len() as f64
+}
+
+#[derive(Clone, Copy)]
+enum Choice {
+    /// The step is matched to an earlier segment, or none.
+    Earlier,
+    /// The step is matched to this segment.
+    Here,
+    /// The step is left unlinked.
+    Skip,
+}
+
+/// Start time, in seconds, of the segment each step is described in.
+pub fn step_times(instructions: &[String], segments: &[Segment]) -> Vec<Option<f64>> {
+    let steps: Vec<_> = instructions.iter().map(|step| words(step)).collect();
+    let spoken: Vec<_> = segments
+        .iter()
+        .map(|segment| words(&segment.text))
+        .collect();
+
+    let (n, m) = (steps.len(), spoken.len());
+
+    // best[i][j] is the highest total score for the first i steps using only the first j
+    // segments, which maximises the overall match while keeping the steps in order
+    let mut best = vec![vec![0.0; m + 1]; n + 1];
+    let mut choice = vec![vec![Choice::Skip; m + 1]; n + 1];
+
+    for i in 1..=n {
+        for j in 1..=m {
+            let mut cell = (best[i - 1][j], Choice::Skip);
+
+            if best[i][j - 1] > cell.0 {
+                cell = (best[i][j - 1], Choice::Earlier);
+            }
+
+            let here = score(&steps[i - 1], &spoken[j - 1], spoken.get(j));
+            if here >= MIN_SCORE && best[i - 1][j] + here > cell.0 {
+                cell = (best[i - 1][j] + here, Choice::Here);
+            }
+
+            (best[i][j], choice[i][j]) = cell;
+        }
+    }
+
+    let mut times = vec![None; n];
+    let (mut i, mut j) = (n, m);
+
+    while i > 0 && j > 0 {
+        match choice[i][j] {
+            Choice::Earlier => j -= 1,
+            Choice::Here => {
+                times[i - 1] = Some(segments[j - 1].start.into_inner());
+                i -= 1;
+            }
+            Choice::Skip => i -= 1,
+        }
+    }
+
+    times
+}
+
+/// Add `step_times` to a recipe as built by `load_nested_recipe`, empty when it has no
+/// transcript to align against.
+pub fn annotate(recipe: &mut Value) {
+    let instructions: Vec<String> = recipe
+        .get("instructions")
+        .and_then(|instructions| serde_json::from_value(instructions.clone()).ok())
+        .unwrap_or_default();
+
+    let segments: Vec<Segment> = recipe
+        .pointer("/instagram_video/transcript/segments")
+        .and_then(|segments| serde_json::from_value(segments.clone()).ok())
+        .unwrap_or_default();
+
+    let times = if segments.is_empty() {
+        vec![]
+    } else {
+        step_times(&instructions, &segments)
+    };
+
+    if let Value::Object(recipe) = recipe {
+        recipe.insert("step_times".to_string(), times.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segments(texts: &[&str]) -> Vec<Segment> {
+        let segments: Vec<Value> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| json!({"id": i, "start": i * 5, "end": i * 5 + 5, "text": text}))
+            .collect();
+
+        serde_json::from_value(segments.into()).unwrap()
+    }
+
+    fn steps(steps: &[&str]) -> Vec<String> {
+        steps.iter().map(|step| step.to_string()).collect()
+    }
+
+    #[test]
+    fn links_each_step_to_its_segment() {
+        let segments = segments(&[
+            "First chop the onions and garlic",
+            "Fry them in butter until golden",
+            "Stir in the cream and simmer",
+        ]);
+        let steps = steps(&[
+            "Chop the onion and garlic",
+            "Fry in butter until golden",
+            "Add cream and simmer",
+        ]);
+
+        assert_eq!(
+            step_times(&steps, &segments),
+            [Some(0.0), Some(5.0), Some(10.0)]
+        );
+    }
+
+    #[test]
+    fn never_goes_back_in_the_video() {
+        // The second step is said first, linking it too would leave the better first step out
+        let segments = segments(&[
+            "Whisk the eggs until pale",
+            "Preheat the oven",
+            "Fold in the flour",
+        ]);
+        let steps = steps(&["Fold in the flour", "Whisk the eggs and sugar"]);
+
+        assert_eq!(step_times(&steps, &segments), [Some(10.0), None]);
+    }
+
+    #[test]
+    fn leaves_unmatched_steps_unlinked() {
+        let segments = segments(&["Chop the onions", "Bake for twenty minutes"]);
+        let steps = steps(&[
+            "Chop the onions",
+            "Season generously with salt and pepper",
+            "Bake for twenty minutes",
+        ]);
+
+        assert_eq!(step_times(&steps, &segments), [Some(0.0), None, Some(5.0)]);
+    }
+
+    #[test]
+    fn annotates_only_recipes_with_a_transcript() {
+        let mut without = json!({"instructions": ["Chop the onions"]});
+        annotate(&mut without);
+        assert_eq!(without["step_times"], json!([]));
+
+        let mut with = json!({
+            "instructions": ["Chop the onions"],
+            "instagram_video": {"transcript": {"segments": [
+                {"id": 0, "start": 2.5, "end": 4.0, "text": "Chop the onions"}
+            ]}},
+        });
+        annotate(&mut with);
+        assert_eq!(with["step_times"], json!([2.5]));
+    }
+}
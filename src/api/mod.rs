@@ -0,0 +1,286 @@
+//! The versioned JSON API, authenticated with personal API tokens rather than session cookies so
+//! scripts and shortcuts don't have to log in.
+
+pub mod tokens;
+mod v1;
+
+pub(crate) use v1::find_visible_video;
+pub use v1::V1Api;
+
+use crate::auth::{self, CurrentUser};
+use crate::entities::api_tokens;
+use crate::entities::prelude::ApiTokens;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::error::{self, AppError};
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use sea_orm::sea_query::Expr;
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, FromQueryResult,
+    PaginatorTrait, QueryFilter, SelectorTrait, Statement,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+pub fn router() -> Router {
+    Router::new().nest("/api/v1", v1::router())
+}
+
+/// What a token is allowed to do, on top of the household role of the user it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "recipes:read")]
+    RecipesRead,
+    #[serde(rename = "recipes:write")]
+    RecipesWrite,
+    #[serde(rename = "videos:read")]
+    VideosRead,
+    #[serde(rename = "videos:write")]
+    VideosWrite,
+    #[serde(rename = "jobs:read")]
+    JobsRead,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [
+        Scope::RecipesRead,
+        Scope::RecipesWrite,
+        Scope::VideosRead,
+        Scope::VideosWrite,
+        Scope::JobsRead,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::RecipesRead => "recipes:read",
+            Scope::RecipesWrite => "recipes:write",
+            Scope::VideosRead => "videos:read",
+            Scope::VideosWrite => "videos:write",
+            Scope::JobsRead => "jobs:read",
+        }
+    }
+
+    /// Writing needs edit rights in the household, reading only needs to be a member.
+    fn role(self) -> HouseholdRole {
+        match self {
+            Scope::RecipesWrite | Scope::VideosWrite => HouseholdRole::Editor,
+            Scope::RecipesRead | Scope::VideosRead | Scope::JobsRead => HouseholdRole::Viewer,
+        }
+    }
+}
+
+#[derive(Debug, FromQueryResult)]
+struct TokenUser {
+    token_id: i32,
+    scopes: Vec<String>,
+    id: i32,
+    username: String,
+    household_id: i32,
+    role: HouseholdRole,
+}
+
+/// A user authenticated by an `Authorization: Bearer` API token.
+#[derive(Debug, Clone)]
+pub struct ApiUser {
+    pub user: CurrentUser,
+    scopes: Vec<String>,
+}
+
+impl ApiUser {
+    /// Fail with `Forbidden` unless the token has `scope` and the user's role allows it.
+    pub fn require(&self, scope: Scope) -> error::Result<()> {
+        if !self.scopes.iter().any(|s| s == scope.as_str()) {
+            return Err(AppError::Forbidden);
+        }
+
+        self.user.require(scope.role())
+    }
+}
+
+fn unauthorized() -> Response {
+    ([(WWW_AUTHENTICATE, "Bearer")], AppError::Unauthorized).into_response()
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(db) = parts.extensions.get::<DatabaseConnection>() else {
+            tracing::error!("No database connection available to authenticate request");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        };
+
+        let Some(token) = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|hv| hv.to_str().ok())
+            .and_then(|hv| hv.strip_prefix("Bearer "))
+        else {
+            return Err(unauthorized());
+        };
+
+        let found = TokenUser::find_by_statement(Statement::from_sql_and_values(
+            Postgres,
+            r#"
+        select t.id as token_id, t.scopes, u.id, u.username, m.household_id, m.role::text as role
+from api_tokens t
+         join users u on u.id = t.user_id
+         join household_members m on m.user_id = u.id
+where t.token_hash = $1
+  and (t.expires_at is null or t.expires_at > now());
+        "#,
+            vec![auth::hash_token(token.trim()).into()],
+        ))
+        .one(db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to look up API token: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+        let Some(found) = found else {
+            return Err(unauthorized());
+        };
+
+        let touched = ApiTokens::update_many()
+            .col_expr(
+                api_tokens::Column::LastUsedAt,
+                Expr::value(chrono::Utc::now().fixed_offset()),
+            )
+            .filter(api_tokens::Column::Id.eq(found.token_id))
+            .exec(db)
+            .await;
+
+        if let Err(e) = touched {
+            tracing::warn!("Failed to record API token use: {e:?}");
+        }
+
+        Ok(ApiUser {
+            user: CurrentUser {
+                id: found.id,
+                username: found.username,
+                household_id: found.household_id,
+                role: found.role,
+            },
+            scopes: found.scopes,
+        })
+    }
+}
+
+const DEFAULT_PER_PAGE: u64 = 25;
+const MAX_PER_PAGE: u64 = 100;
+/// Past any real page, and low enough that its offset fits in Postgres' `bigint`.
+const MAX_PAGE: u64 = i64::MAX as u64 / MAX_PER_PAGE;
+
+/// `?page=` and `?per_page=`, pages start at 1.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Page number, starting at 1.
+    page: Option<u64>,
+    /// Items per page, at most 100.
+    per_page: Option<u64>,
+}
+
+impl PageQuery {
+    fn page(&self) -> u64 {
+        self.page.unwrap_or(1).clamp(1, MAX_PAGE)
+    }
+
+    fn per_page(&self) -> u64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    fn offset(&self) -> u64 {
+        (self.page() - 1) * self.per_page()
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Pagination {
+    page: u64,
+    per_page: u64,
+    total: u64,
+    total_pages: u64,
+}
+
+/// The envelope every list endpoint responds with.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Page<T> {
+    data: Vec<T>,
+    pagination: Pagination,
+}
+
+impl<T: Serialize> Page<T> {
+    fn new(data: Vec<T>, query: &PageQuery, total: u64) -> Self {
+        let per_page = query.per_page();
+
+        Page {
+            data,
+            pagination: Pagination {
+                page: query.page(),
+                per_page,
+                total,
+                total_pages: total.div_ceil(per_page),
+            },
+        }
+    }
+
+    async fn fetch<'db, C, S>(select: S, query: &PageQuery, db: &'db C) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+        S: PaginatorTrait<'db, C>,
+        S::Selector: SelectorTrait<Item = T>,
+    {
+        let paginator = select.paginate(db, query.per_page());
+        let total = paginator.num_items().await?;
+        let data = paginator.fetch_page(query.page() - 1).await?;
+
+        Ok(Page::new(data, query, total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_query(page: Option<u64>, per_page: Option<u64>) -> PageQuery {
+        PageQuery { page, per_page }
+    }
+
+    #[test]
+    fn defaults_to_the_first_page() {
+        let query = page_query(None, None);
+        assert_eq!(
+            (query.page(), query.per_page(), query.offset()),
+            (1, DEFAULT_PER_PAGE, 0)
+        );
+    }
+
+    #[test]
+    fn clamps_out_of_range_values() {
+        let query = page_query(Some(0), Some(1000));
+        assert_eq!((query.page(), query.per_page()), (1, MAX_PER_PAGE));
+
+        let query = page_query(Some(3), Some(0));
+        assert_eq!((query.per_page(), query.offset()), (1, 2));
+    }
+
+    #[test]
+    fn huge_pages_still_have_an_offset_postgres_takes() {
+        let query = page_query(Some(u64::MAX), Some(MAX_PER_PAGE));
+        assert!(query.offset() <= i64::MAX as u64);
+    }
+}
@@ -0,0 +1,140 @@
+use super::Scope;
+use crate::auth::{self, CurrentUser};
+use crate::entities::api_tokens;
+use crate::entities::prelude::ApiTokens;
+use crate::error::{self, AppError};
+use anyhow::anyhow;
+use axum::extract::Path;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Extension;
+use axum_extra::extract::Form;
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use minijinja_autoreload::AutoReloader;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+async fn render_tokens(
+    user: &CurrentUser,
+    db: &DatabaseConnection,
+    template_engine: Engine<AutoReloader>,
+    created: Option<String>,
+) -> error::Result<Response> {
+    let tokens = ApiTokens::find()
+        .filter(api_tokens::Column::UserId.eq(user.id))
+        .order_by_desc(api_tokens::Column::CreatedAt)
+        .all(db)
+        .await?;
+
+    Ok(RenderHtml(
+        "tokens/index.html",
+        template_engine,
+        json!({
+            "tokens": tokens,
+            "created": created,
+            "scopes": Scope::ALL.map(Scope::as_str),
+        }),
+    )
+    .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/settings/tokens",
+    tag = "tokens",
+    responses((status = 200, content_type = "text/html"))
+)]
+pub async fn tokens_index(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> error::Result<Response> {
+    render_tokens(&user, &db, template_engine, None).await
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateTokenRequest {
+    name: String,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>, example = json!(["recipes:read"]))]
+    scopes: Vec<Scope>,
+    expires_in_days: Option<i64>,
+}
+
+/// The token is only shown once as we only keep its hash.
+#[utoipa::path(
+    post,
+    path = "/settings/tokens",
+    tag = "tokens",
+    request_body(content_type = "application/x-www-form-urlencoded", content = inline(CreateTokenRequest)),
+    responses(
+        (status = 200, description = "The tokens page showing the new token", content_type = "text/html"),
+        (status = 400, description = "Missing a name or scopes"),
+    )
+)]
+pub async fn create_token(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(request): Form<CreateTokenRequest>,
+) -> error::Result<Response> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::BadRequest(anyhow!("Tokens need a name")));
+    }
+
+    if request.scopes.is_empty() {
+        return Err(AppError::BadRequest(anyhow!(
+            "Tokens need at least one scope"
+        )));
+    }
+
+    let token = auth::generate_token();
+    let now = chrono::Utc::now();
+
+    api_tokens::ActiveModel {
+        user_id: Set(user.id),
+        name: Set(request.name.trim().to_string()),
+        token_hash: Set(auth::hash_token(&token)),
+        scopes: Set(request
+            .scopes
+            .iter()
+            .map(|s| s.as_str().to_string())
+            .collect()),
+        created_at: Set(now.fixed_offset()),
+        expires_at: Set(request
+            .expires_in_days
+            .filter(|days| *days > 0)
+            .map(|days| (now + chrono::Duration::days(days)).fixed_offset())),
+
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+
+    render_tokens(&user, &db, template_engine, Some(token)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/settings/tokens/{id}/revoke",
+    tag = "tokens",
+    params(("id" = i32, Path)),
+    responses((status = 303, description = "Token revoked"))
+)]
+pub async fn revoke_token(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<impl IntoResponse> {
+    ApiTokens::delete_many()
+        .filter(api_tokens::Column::Id.eq(id))
+        .filter(api_tokens::Column::UserId.eq(user.id))
+        .exec(&db)
+        .await?;
+
+    Ok(Redirect::to("/settings/tokens"))
+}
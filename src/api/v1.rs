@@ -0,0 +1,533 @@
+use super::{ApiUser, Page, PageQuery, Scope};
+use crate::collections::{self, CollectionSummary};
+use crate::entities::prelude::{CollectionRecipes, InstagramVideo, Recipes};
+use crate::entities::{collection_recipes, household_members, instagram_video, recipes};
+use crate::error::{self, AppError, Problem};
+use crate::jobs;
+use crate::jobs::extract_transcript::ExtractTranscriptJob;
+use crate::jobs::fetch_post::FetchJob;
+use crate::jobs::llm_extract_details::LLmExtractDetailsJob;
+use crate::FangQueue;
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use sea_orm::prelude::Uuid;
+use sea_orm::sea_query::Query as SeaQuery;
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    QueryOrder, QuerySelect, Statement,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/recipes", get(list_recipes).post(create_recipe))
+        .route("/recipes/text", post(create_recipe_from_text))
+        .route("/recipes/:id", get(show_recipe))
+        .route("/collections", get(list_collections))
+        .route("/videos", get(list_videos))
+        .route("/videos/:id", get(show_video))
+        .route("/videos/:id/transcribe", post(transcribe_video))
+        .route("/videos/:id/llm", post(extract_details))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/:id", get(show_job))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        list_recipes,
+        create_recipe,
+        create_recipe_from_text,
+        show_recipe,
+        list_collections,
+        list_videos,
+        show_video,
+        transcribe_video,
+        extract_details,
+        list_jobs,
+        show_job,
+    ),
+    security(("api_token" = []))
+)]
+pub struct V1Api;
+
+#[derive(Serialize, ToSchema)]
+struct JobAccepted {
+    /// Poll `GET /api/v1/jobs/{job_id}` for progress.
+    job_id: String,
+}
+
+fn job_accepted(id: Uuid) -> Response {
+    (
+        StatusCode::ACCEPTED,
+        Json(JobAccepted {
+            job_id: id.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RecipesFilter {
+    /// Only recipes in this collection.
+    collection: Option<i32>,
+}
+
+/// Recipes in the household's library.
+#[utoipa::path(
+    get,
+    path = "/recipes",
+    tag = "api/v1",
+    operation_id = "v1_list_recipes",
+    params(PageQuery, RecipesFilter),
+    responses(
+        (status = 200, body = Page<recipes::Model>),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the recipes:read scope"),
+    )
+)]
+async fn list_recipes(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Query(page): Query<PageQuery>,
+    Query(filter): Query<RecipesFilter>,
+) -> error::Result<Json<Page<recipes::Model>>> {
+    api.require(Scope::RecipesRead)?;
+
+    let mut recipes = Recipes::find()
+        .filter(recipes::Column::HouseholdId.eq(api.user.household_id))
+        .order_by_asc(recipes::Column::Id);
+
+    if let Some(collection) = filter.collection {
+        recipes = recipes
+            .inner_join(CollectionRecipes)
+            .filter(collection_recipes::Column::CollectionId.eq(collection));
+    }
+
+    Ok(Json(Page::fetch(recipes, &page, &db).await?))
+}
+
+/// The household's collections by name, filter `GET /recipes` by one to list its recipes.
+#[utoipa::path(
+    get,
+    path = "/collections",
+    tag = "api/v1",
+    operation_id = "v1_list_collections",
+    params(PageQuery),
+    responses(
+        (status = 200, body = Page<CollectionSummary>),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the recipes:read scope"),
+    )
+)]
+async fn list_collections(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Query(page): Query<PageQuery>,
+) -> error::Result<Json<Page<CollectionSummary>>> {
+    api.require(Scope::RecipesRead)?;
+
+    let collections = collections::summaries(api.user.household_id);
+    Ok(Json(Page::fetch(collections, &page, &db).await?))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct CreateRecipeRequest {
+    /// A reel, or an image post's `/p/` link.
+    reel_url: String,
+    #[serde(default)]
+    auto_llm: bool,
+}
+
+/// Queue a reel or image post to be fetched, responding with the job to poll.
+#[utoipa::path(
+    post,
+    path = "/recipes",
+    tag = "api/v1",
+    operation_id = "v1_create_recipe",
+    request_body = CreateRecipeRequest,
+    responses(
+        (status = 202, body = JobAccepted),
+        (status = 400, description = "Not an Instagram reel or post URL"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the recipes:write scope or edit rights"),
+    )
+)]
+async fn create_recipe(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(mut queue): Extension<FangQueue>,
+    Json(request): Json<CreateRecipeRequest>,
+) -> error::Result<Response> {
+    api.require(Scope::RecipesWrite)?;
+
+    let job = FetchJob::new(
+        request.reel_url,
+        request.auto_llm,
+        api.user.id,
+        api.user.household_id,
+    )
+    .map_err(AppError::BadRequest)?;
+
+    let task = jobs::enqueue(&mut queue, &db, job.job()).await?;
+    Ok(job_accepted(task.id))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct CreateRecipeFromTextRequest {
+    /// A recipe as written in an email, message or note.
+    text: String,
+}
+
+/// Queue recipe extraction from pasted text, responding with the job to poll.
+#[utoipa::path(
+    post,
+    path = "/recipes/text",
+    tag = "api/v1",
+    operation_id = "v1_create_recipe_from_text",
+    request_body = CreateRecipeFromTextRequest,
+    responses(
+        (status = 202, body = JobAccepted),
+        (status = 400, description = "No text"),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the recipes:write scope or edit rights"),
+    )
+)]
+async fn create_recipe_from_text(
+    api: ApiUser,
+    Extension(mut queue): Extension<FangQueue>,
+    Extension(db): Extension<DatabaseConnection>,
+    Json(request): Json<CreateRecipeFromTextRequest>,
+) -> error::Result<Response> {
+    api.require(Scope::RecipesWrite)?;
+
+    let job = crate::text_extraction_job(request.text, &api.user)?;
+    let task = jobs::enqueue(&mut queue, &db, &job).await?;
+    Ok(job_accepted(task.id))
+}
+
+/// A recipe with the video it came from nested.
+#[utoipa::path(
+    get,
+    path = "/recipes/{id}",
+    tag = "api/v1",
+    operation_id = "v1_show_recipe",
+    params(("id" = i32, Path, description = "Recipe id")),
+    responses(
+        (status = 200, body = crate::NestedRecipe),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn show_recipe(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<Response> {
+    api.require(Scope::RecipesRead)?;
+
+    let recipe = crate::load_nested_recipe(id, api.user.household_id, &db).await?;
+    Ok(Json(recipe).into_response())
+}
+
+/// Videos imported by someone in the household, or that one of its recipes came from.
+fn visible_videos(household_id: i32) -> Condition {
+    Condition::any()
+        .add(
+            instagram_video::Column::OwnerId.in_subquery(
+                SeaQuery::select()
+                    .column(household_members::Column::UserId)
+                    .from(household_members::Entity)
+                    .and_where(household_members::Column::HouseholdId.eq(household_id))
+                    .to_owned(),
+            ),
+        )
+        .add(
+            instagram_video::Column::Id.in_subquery(
+                SeaQuery::select()
+                    .column(recipes::Column::InstagramVideoId)
+                    .from(recipes::Entity)
+                    .and_where(recipes::Column::HouseholdId.eq(household_id))
+                    .to_owned(),
+            ),
+        )
+}
+
+#[derive(Serialize, FromQueryResult, ToSchema)]
+struct VideoSummary {
+    id: i32,
+    instagram_id: String,
+    video_url: String,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Videos the household can see, without the (large) info and transcript of each.
+#[utoipa::path(
+    get,
+    path = "/videos",
+    tag = "api/v1",
+    operation_id = "v1_list_videos",
+    params(PageQuery),
+    responses(
+        (status = 200, body = Page<VideoSummary>),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn list_videos(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Query(page): Query<PageQuery>,
+) -> error::Result<Json<Page<VideoSummary>>> {
+    api.require(Scope::VideosRead)?;
+
+    let videos = InstagramVideo::find()
+        .select_only()
+        .columns([
+            instagram_video::Column::Id,
+            instagram_video::Column::InstagramId,
+            instagram_video::Column::VideoUrl,
+            instagram_video::Column::CreatedAt,
+            instagram_video::Column::UpdatedAt,
+        ])
+        .filter(visible_videos(api.user.household_id))
+        .order_by_asc(instagram_video::Column::Id)
+        .into_model::<VideoSummary>();
+
+    Ok(Json(Page::fetch(videos, &page, &db).await?))
+}
+
+/// A video with its info and transcript.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}",
+    tag = "api/v1",
+    operation_id = "v1_show_video",
+    params(("id" = i32, Path, description = "Video id")),
+    responses(
+        (status = 200, body = instagram_video::Model),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn show_video(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<Response> {
+    api.require(Scope::VideosRead)?;
+
+    let video = InstagramVideo::find_by_id(id)
+        .filter(visible_videos(api.user.household_id))
+        .one(&db)
+        .await?;
+
+    match video {
+        Some(video) => Ok(Json(video).into_response()),
+        None => Err(AppError::NotFound("Video")),
+    }
+}
+
+/// `id` if the household can see the video, so work on it can only be queued by its own users.
+pub(crate) async fn find_visible_video(
+    db: &DatabaseConnection,
+    household_id: i32,
+    id: i32,
+) -> error::Result<Option<i32>> {
+    Ok(InstagramVideo::find_by_id(id)
+        .select_only()
+        .column(instagram_video::Column::Id)
+        .filter(visible_videos(household_id))
+        .into_tuple::<i32>()
+        .one(db)
+        .await?)
+}
+
+/// Queue the video to be transcribed again.
+#[utoipa::path(
+    post,
+    path = "/videos/{id}/transcribe",
+    tag = "api/v1",
+    operation_id = "v1_transcribe_video",
+    params(("id" = i32, Path, description = "Video id")),
+    responses(
+        (status = 202, body = JobAccepted),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn transcribe_video(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(mut queue): Extension<FangQueue>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<Response> {
+    api.require(Scope::VideosWrite)?;
+
+    let Some(id) = find_visible_video(&db, api.user.household_id, id).await? else {
+        return Err(AppError::NotFound("Video"));
+    };
+
+    let job = ExtractTranscriptJob::new(id, &db).await?;
+    let task = jobs::enqueue(&mut queue, &db, &job).await?;
+    Ok(job_accepted(task.id))
+}
+
+/// Queue recipe extraction from the video's transcript.
+#[utoipa::path(
+    post,
+    path = "/videos/{id}/llm",
+    tag = "api/v1",
+    operation_id = "v1_extract_details",
+    params(("id" = i32, Path, description = "Video id")),
+    responses(
+        (status = 202, body = JobAccepted),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn extract_details(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(mut queue): Extension<FangQueue>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<Response> {
+    api.require(Scope::VideosWrite)?;
+
+    let Some(id) = find_visible_video(&db, api.user.household_id, id).await? else {
+        return Err(AppError::NotFound("Video"));
+    };
+
+    let job = LLmExtractDetailsJob::new(id, Some(api.user.id), Some(api.user.household_id));
+    let task = jobs::enqueue(&mut queue, &db, &job).await?;
+    Ok(job_accepted(task.id))
+}
+
+#[derive(Serialize, FromQueryResult, ToSchema)]
+struct JobSummary {
+    id: String,
+    kind: Option<String>,
+    task_type: String,
+    state: String,
+    error_message: Option<String>,
+    retries: i32,
+    scheduled_at: chrono::DateTime<chrono::FixedOffset>,
+    created_at: chrono::DateTime<chrono::FixedOffset>,
+    updated_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// Jobs queued for the household, or for any of the videos it can see. `$1` is the household.
+const VISIBLE_JOBS: &str = r#"
+        select t.id::text as id,
+       t.metadata ->> 'type' as kind,
+       t.task_type,
+       t.state::text as state,
+       t.error_message,
+       t.retries,
+       t.scheduled_at,
+       t.created_at,
+       t.updated_at
+from fang_tasks t
+where (t.metadata ->> 'household_id')::integer = $1
+   or (t.metadata ->> 'video_id')::integer in (select iv.id
+                                               from instagram_video iv
+                                               where iv.owner_id in (select m.user_id
+                                                                     from household_members m
+                                                                     where m.household_id = $1)
+                                                  or iv.id in (select r.instagram_video_id
+                                                               from recipes r
+                                                               where r.household_id = $1))
+"#;
+
+/// Jobs queued for the household, most recent first.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "api/v1",
+    operation_id = "v1_list_jobs",
+    params(PageQuery),
+    responses(
+        (status = 200, body = Page<JobSummary>),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn list_jobs(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Query(page): Query<PageQuery>,
+) -> error::Result<Json<Page<JobSummary>>> {
+    api.require(Scope::JobsRead)?;
+
+    #[derive(FromQueryResult)]
+    struct Count {
+        count: i64,
+    }
+
+    let total = Count::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        format!("select count(*) as count from ({VISIBLE_JOBS}) jobs;"),
+        vec![api.user.household_id.into()],
+    ))
+    .one(&db)
+    .await?
+    .map_or(0, |c| c.count as u64);
+
+    let jobs = JobSummary::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        format!("{VISIBLE_JOBS} order by t.created_at desc limit $2 offset $3;"),
+        vec![
+            api.user.household_id.into(),
+            (page.per_page() as i64).into(),
+            (page.offset() as i64).into(),
+        ],
+    ))
+    .all(&db)
+    .await?;
+
+    Ok(Json(Page::new(jobs, &page, total)))
+}
+
+/// A queued job. The queue removes jobs once they finish or give up, so a 404 for a job we
+/// accepted means it is over.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "api/v1",
+    operation_id = "v1_show_job",
+    params(("id" = String, Path, format = Uuid, description = "Job id")),
+    responses(
+        (status = 200, body = JobSummary),
+        (status = 401, body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    )
+)]
+async fn show_job(
+    api: ApiUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((id,)): Path<(String,)>,
+) -> error::Result<Response> {
+    api.require(Scope::JobsRead)?;
+
+    let Ok(id) = Uuid::parse_str(&id) else {
+        return Err(AppError::NotFound("Job"));
+    };
+
+    let job = JobSummary::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        format!("select * from ({VISIBLE_JOBS}) jobs where jobs.id = $2;"),
+        vec![api.user.household_id.into(), id.to_string().into()],
+    ))
+    .one(&db)
+    .await?;
+
+    match job {
+        Some(job) => Ok(Json(job).into_response()),
+        None => Err(AppError::NotFound("Job")),
+    }
+}
@@ -0,0 +1,361 @@
+use crate::cli::CreateUserArgs;
+use crate::entities::prelude::{Sessions, Users};
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::entities::{instagram_video, recipes, sessions, users};
+use crate::error::{self, AppError};
+use crate::households;
+use crate::negotiate;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::CookieJar;
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use minijinja_autoreload::AutoReloader;
+use sea_orm::sea_query::Expr;
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    Set, Statement,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+pub const SESSION_COOKIE: &str = "recipes_session";
+
+const SESSION_DAYS: i64 = 30;
+
+/// The user a request was made by, rejecting with a redirect to `/login` (or a 401 for JSON
+/// clients) when there is no valid session.
+#[derive(Debug, Clone, FromQueryResult)]
+pub struct CurrentUser {
+    pub id: i32,
+    pub username: String,
+    pub household_id: i32,
+    pub role: HouseholdRole,
+}
+
+impl CurrentUser {
+    /// Fail with `Forbidden` unless the user's household role is at least `role`.
+    pub fn require(&self, role: HouseholdRole) -> error::Result<()> {
+        if self.role.rank() >= role.rank() {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden)
+        }
+    }
+}
+
+impl HouseholdRole {
+    fn rank(self) -> u8 {
+        match self {
+            HouseholdRole::Viewer => 0,
+            HouseholdRole::Editor => 1,
+            HouseholdRole::Owner => 2,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let unauthorized = || {
+            if negotiate::wants_json(&parts.headers) {
+                AppError::Unauthorized.into_response()
+            } else {
+                let next = parts.uri.path_and_query().map_or("/", |pq| pq.as_str());
+                let query = serde_urlencoded::to_string([("next", next)]).unwrap_or_default();
+                Redirect::to(&format!("/login?{query}")).into_response()
+            }
+        };
+
+        let Some(db) = parts.extensions.get::<DatabaseConnection>() else {
+            tracing::error!("No database connection available to authenticate request");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        };
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        let Some(token) = jar.get(SESSION_COOKIE) else {
+            return Err(unauthorized());
+        };
+
+        let user = CurrentUser::find_by_statement(Statement::from_sql_and_values(
+            Postgres,
+            r#"
+        select u.id, u.username, m.household_id, m.role::text as role
+from sessions s
+         join users u on u.id = s.user_id
+         join household_members m on m.user_id = u.id
+where s.token_hash = $1
+  and s.expires_at > now();
+        "#,
+            vec![hash_token(token.value()).into()],
+        ))
+        .one(db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to look up session: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+        user.ok_or_else(unauthorized)
+    }
+}
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {e}"))?;
+
+    Ok(hash.to_string())
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Tokens are stored by their hash so a database leak doesn't leak sessions or invitations.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+async fn create_session(db: &DatabaseConnection, user_id: i32) -> anyhow::Result<String> {
+    let token = generate_token();
+
+    let now = chrono::Utc::now();
+    sessions::ActiveModel {
+        token_hash: Set(hash_token(&token)),
+        user_id: Set(user_id),
+        created_at: Set(now.fixed_offset()),
+        expires_at: Set((now + chrono::Duration::days(SESSION_DAYS)).fixed_offset()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Backs the `create-user` subcommand, there is deliberately no public sign up.
+pub async fn create_user(db: &DatabaseConnection, args: &CreateUserArgs) -> anyhow::Result<()> {
+    let user = users::ActiveModel {
+        username: Set(args.username.clone()),
+        password_hash: Set(hash_password(&args.password)?),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    tracing::info!("Created user {} with id {}", user.username, user.id);
+
+    let household = households::create_for(db, &user).await?;
+
+    if args.adopt_unowned {
+        let videos = instagram_video::Entity::update_many()
+            .col_expr(instagram_video::Column::OwnerId, Expr::value(user.id))
+            .filter(instagram_video::Column::OwnerId.is_null())
+            .exec(db)
+            .await?;
+
+        let recipes = recipes::Entity::update_many()
+            .col_expr(recipes::Column::OwnerId, Expr::value(user.id))
+            .col_expr(recipes::Column::HouseholdId, Expr::value(household.id))
+            .filter(recipes::Column::OwnerId.is_null())
+            .exec(db)
+            .await?;
+
+        tracing::info!(
+            "Assigned {} videos and {} recipes to {}",
+            videos.rows_affected,
+            recipes.rows_affected,
+            user.username
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+    next: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+/// Only follow local redirects after logging in. Browsers read `//host` and `/\host` as another
+/// site, and drop tabs and newlines before doing so.
+fn local_redirect(next: Option<&str>) -> &str {
+    match next {
+        Some(next)
+            if next.starts_with('/')
+                && !next[1..].starts_with(['/', '\\'])
+                && !next.chars().any(|c| c.is_ascii_control()) =>
+        {
+            next
+        }
+        _ => "/recipes",
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/login",
+    tag = "auth",
+    security(()),
+    params(("next" = Option<String>, Query, description = "Local path to return to")),
+    responses((status = 200, content_type = "text/html"))
+)]
+pub async fn login_page(
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Query(query): Query<LoginQuery>,
+) -> impl IntoResponse {
+    RenderHtml(
+        "auth/login.html",
+        template_engine,
+        json!({ "next": local_redirect(query.next.as_deref()) }),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    security(()),
+    request_body(content_type = "application/x-www-form-urlencoded", content = inline(LoginRequest)),
+    responses(
+        (status = 303, description = "Logged in, sets the session cookie"),
+        (status = 401, description = "Invalid username or password", content_type = "text/html"),
+    )
+)]
+pub async fn login(
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+    jar: CookieJar,
+    Form(request): Form<LoginRequest>,
+) -> error::Result<Response> {
+    let user = Users::find()
+        .filter(users::Column::Username.eq(&request.username))
+        .one(&db)
+        .await?;
+
+    let Some(user) = user.filter(|user| verify_password(&request.password, &user.password_hash))
+    else {
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            RenderHtml(
+                "auth/login.html",
+                template_engine,
+                json!({
+                    "username": request.username,
+                    "next": local_redirect(request.next.as_deref()),
+                    "error": "Invalid username or password",
+                }),
+            ),
+        )
+            .into_response());
+    };
+
+    Sessions::delete_many()
+        .filter(sessions::Column::UserId.eq(user.id))
+        .filter(sessions::Column::ExpiresAt.lte(chrono::Utc::now()))
+        .exec(&db)
+        .await?;
+
+    let token = create_session(&db, user.id).await?;
+    let cookie = Cookie::build((SESSION_COOKIE, token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::days(SESSION_DAYS));
+
+    Ok((
+        jar.add(cookie),
+        Redirect::to(local_redirect(request.next.as_deref())),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/logout",
+    tag = "auth",
+    responses((status = 303, description = "Logged out, clears the session cookie"))
+)]
+pub async fn logout(
+    Extension(db): Extension<DatabaseConnection>,
+    jar: CookieJar,
+) -> error::Result<impl IntoResponse> {
+    if let Some(token) = jar.get(SESSION_COOKIE) {
+        Sessions::delete_by_id(hash_token(token.value()))
+            .exec(&db)
+            .await?;
+    }
+
+    Ok((
+        jar.remove(Cookie::build(SESSION_COOKIE).path("/")),
+        Redirect::to("/login"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_local_paths() {
+        assert_eq!(local_redirect(Some("/recipes/12")), "/recipes/12");
+        assert_eq!(
+            local_redirect(Some("/share?url=https://example.com")),
+            "/share?url=https://example.com"
+        );
+        assert_eq!(local_redirect(None), "/recipes");
+    }
+
+    #[test]
+    fn refuses_other_sites() {
+        for next in [
+            "https://evil.com",
+            "//evil.com",
+            "/\\evil.com",
+            "/\t/evil.com",
+            "/\n/evil.com",
+            "evil.com",
+            "",
+        ] {
+            assert_eq!(local_redirect(Some(next)), "/recipes", "{next:?}");
+        }
+    }
+}
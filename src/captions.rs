@@ -0,0 +1,189 @@
+//! Captions rendered from a video's transcript segments, as WebVTT for the player's `<track>`
+//! and SRT for anything else.
+
+use crate::auth::CurrentUser;
+use crate::entities::instagram_video;
+use crate::entities::prelude::InstagramVideo;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::error::{self, AppError};
+use crate::jobs::extract_transcript::Segment;
+use axum::extract::Path;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::IntoResponse;
+use axum::Extension;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+/// `HH:MM:SS` then `separator` and milliseconds, WebVTT uses `.` and SRT `,`.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Cue text on one line, a blank line would end the cue early.
+fn cue_text(segment: &Segment) -> String {
+    segment
+        .text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn webvtt(segments: &[Segment]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+
+    for segment in segments {
+        // Cue text is HTML-like, and the arrow would be read as the timing line
+        let text = cue_text(segment)
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{text}\n",
+            timestamp(segment.start.into_inner(), '.'),
+            timestamp(segment.end.into_inner(), '.'),
+        ));
+    }
+
+    vtt
+}
+
+pub fn srt(segments: &[Segment]) -> String {
+    let mut srt = String::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            timestamp(segment.start.into_inner(), ','),
+            timestamp(segment.end.into_inner(), ','),
+            cue_text(segment),
+        ));
+    }
+
+    srt
+}
+
+async fn load_segments(db: &DatabaseConnection, instagram_id: &str) -> error::Result<Vec<Segment>> {
+    let video = InstagramVideo::find()
+        .filter(instagram_video::Column::InstagramId.eq(instagram_id))
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Video"))?;
+
+    let transcript = video.transcript.ok_or(AppError::NotFound("Transcript"))?;
+    Ok(transcript.segments)
+}
+
+/// The transcript as WebVTT captions, for the recipe page's video player.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}/captions.vtt",
+    tag = "videos",
+    params(("id" = String, Path, description = "Instagram id of the reel")),
+    responses(
+        (status = 200, description = "The captions", content_type = "text/vtt"),
+        (status = 404, description = "No such video, or it hasn't been transcribed"),
+    )
+)]
+pub async fn webvtt_captions(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((instagram_id,)): Path<(String,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let segments = load_segments(&db, &instagram_id).await?;
+    Ok((
+        [(CONTENT_TYPE, "text/vtt; charset=utf-8")],
+        webvtt(&segments),
+    ))
+}
+
+/// The transcript as an SRT subtitle file, downloaded as `<id>.srt`.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}/captions.srt",
+    tag = "videos",
+    params(("id" = String, Path, description = "Instagram id of the reel")),
+    responses(
+        (status = 200, description = "The captions", content_type = "application/x-subrip"),
+        (status = 404, description = "No such video, or it hasn't been transcribed"),
+    )
+)]
+pub async fn srt_captions(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((instagram_id,)): Path<(String,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let segments = load_segments(&db, &instagram_id).await?;
+    let disposition = format!("attachment; filename=\"{instagram_id}.srt\"");
+
+    Ok((
+        [
+            (
+                CONTENT_TYPE,
+                "application/x-subrip; charset=utf-8".to_string(),
+            ),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        srt(&segments),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn segments(segments: Value) -> Vec<Segment> {
+        serde_json::from_value(segments).unwrap()
+    }
+
+    #[test]
+    fn timestamps_round_to_the_millisecond() {
+        assert_eq!(timestamp(0.0, '.'), "00:00:00.000");
+        assert_eq!(timestamp(1.0006, '.'), "00:00:01.001");
+        assert_eq!(timestamp(59.9996, ','), "00:01:00,000");
+        assert_eq!(timestamp(3723.25, ','), "01:02:03,250");
+        assert_eq!(timestamp(-0.5, '.'), "00:00:00.000");
+    }
+
+    #[test]
+    fn webvtt_escapes_cue_text() {
+        let segments = segments(json!([
+            {"id": 0, "start": 0.0, "end": 2.5, "text": " Salt & pepper "},
+            {"id": 1, "start": 2.5, "end": 4.0, "text": "Oven --> <b>200</b>\n\nC"},
+        ]));
+
+        assert_eq!(
+            webvtt(&segments),
+            "WEBVTT\n\
+             \n00:00:00.000 --> 00:00:02.500\nSalt &amp; pepper\n\
+             \n00:00:02.500 --> 00:00:04.000\nOven --&gt; &lt;b&gt;200&lt;/b&gt; C\n"
+        );
+    }
+
+    #[test]
+    fn srt_numbers_cues_from_one() {
+        let segments = segments(json!([
+            {"id": 0, "start": 0.0, "end": 1.5, "text": "Chop the onions"},
+            {"id": 1, "start": 1.5, "end": 3.0, "text": "Fry them\n\nin butter"},
+        ]));
+
+        assert_eq!(
+            srt(&segments),
+            "1\n00:00:00,000 --> 00:00:01,500\nChop the onions\n\n\
+             2\n00:00:01,500 --> 00:00:03,000\nFry them in butter\n\n"
+        );
+    }
+}
@@ -1,81 +1,280 @@
+use crate::interop::Manager;
 use crate::jobs::llm_extract_details::LlmMethod;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
 #[derive(Debug, clap::Parser)]
 pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+
     /// Postgres connection url
     #[clap(
         short = 'd',
         long = "db",
         env = "RECIPE_DATABASE_URL",
-        default_value = "postgres://postgres@localhost/recipes"
+        default_value = "postgres://postgres@localhost/recipes",
+        global = true
     )]
     pub database_url: String,
 
-    /// Server address
+    /// Maximum number of connections the job queue holds open
     #[clap(
-        short = 'a',
-        long = "address",
-        env = "RECIPE_ADDRESS",
-        default_value = "0.0.0.0:5005"
+        long = "queue-pool-size",
+        env = "RECIPE_QUEUE_POOL_SIZE",
+        default_value = "2",
+        global = true
     )]
-    pub address: SocketAddr,
+    pub queue_pool_size: u32,
 
     /// Path to youtube-dl if not on PATH
-    #[clap(long = "yt-dlp-path", env = "RECIPE_YT_DLP_PATH")]
+    #[clap(long = "yt-dlp-path", env = "RECIPE_YT_DLP_PATH", global = true)]
     pub yt_dlp_path: Option<PathBuf>,
 
+    /// Path to gallery-dl if not on PATH, used for image posts
+    #[clap(
+        long = "gallery-dl-path",
+        env = "RECIPE_GALLERY_DL_PATH",
+        global = true
+    )]
+    pub gallery_dl_path: Option<PathBuf>,
+
     /// Directory to save reels
     #[clap(
         short = 'r',
         long = "reel-dir",
         env = "RECIPE_REEL_DIR",
-        default_value = "./reels"
+        default_value = "./reels",
+        global = true
     )]
     pub reel_dir: PathBuf,
 
     #[clap(
         long = "whisper-url",
         env = "RECIPE_WHISPER_URL",
-        default_value = "http://127.0.0.1:8080/inference"
+        default_value = "http://127.0.0.1:8080/inference",
+        global = true
     )]
     pub whisper_url: String,
 
     #[clap(
         long = "whisper-key",
         env = "RECIPE_WHISPER_KEY",
-        default_value = "local"
+        default_value = "local",
+        global = true
     )]
     pub whisper_key: String,
 
+    /// Language of the reels' audio, e.g. `en`. By default whisper detects it: `auto` is sent to
+    /// whisper.cpp's `/inference`, which otherwise assumes English, and the field is left out for
+    /// OpenAI's API, which rejects `auto`. Pass an empty value to always leave it out
+    #[clap(
+        long = "whisper-language",
+        env = "RECIPE_WHISPER_LANGUAGE",
+        global = true
+    )]
+    pub whisper_language: Option<String>,
+
+    /// Have whisper translate the transcript into English, as whisper.cpp's server does with
+    /// `translate`. For OpenAI point the whisper url at `/v1/audio/translations` instead
+    #[clap(
+        long = "whisper-translate",
+        env = "RECIPE_WHISPER_TRANSLATE",
+        global = true
+    )]
+    pub whisper_translate: bool,
+
     #[clap(
         long = "completion-url",
         env = "RECIPE_COMPLETION_URL",
-        default_value = "http://localhost:11434/api/generate"
+        default_value = "http://localhost:11434/api/generate",
+        global = true
     )]
     pub completion_url: String,
 
     #[clap(
         long = "completion-key",
         env = "RECIPE_COMPLETION_KEY",
-        default_value = "ollama"
+        default_value = "ollama",
+        global = true
     )]
     pub completion_key: String,
 
     #[clap(
         long = "model",
         env = "RECIPE_COMPLETION_MODEL",
-        default_value = "gemma"
+        default_value = "gemma",
+        global = true
     )]
     pub completion_model: String,
 
     #[clap(
         long = "completion-mode",
         env = "RECIPE_COMPLETION_MODE",
-        default_value = "ollama-json"
+        default_value = "ollama-json",
+        global = true
     )]
     pub completion_mode: LlmMethod,
+
+    /// Language the extracted recipes are written in, whatever language the reel is in
+    #[clap(
+        long = "recipe-language",
+        env = "RECIPE_OUTPUT_LANGUAGE",
+        default_value = "English",
+        global = true
+    )]
+    pub recipe_language: String,
+
+    /// OTLP gRPC endpoint to export traces to, e.g. http://localhost:4317
+    #[clap(long = "otlp-endpoint", env = "RECIPE_OTLP_ENDPOINT", global = true)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Run the web server only, jobs are enqueued for workers elsewhere
+    Serve(ServeArgs),
+
+    /// Run background job workers only
+    Worker(WorkerArgs),
+
+    /// Run the web server and the job workers in one process
+    All {
+        #[clap(flatten)]
+        serve: ServeArgs,
+
+        #[clap(flatten)]
+        worker: WorkerArgs,
+    },
+
+    /// Create a local account
+    CreateUser(CreateUserArgs),
+
+    /// Import the saved posts from an Instagram data export, zipped or extracted
+    ImportInstagram(ImportInstagramArgs),
+
+    /// Import recipes exported from Paprika, Mealie or Tandoor
+    ImportRecipes(ImportRecipesArgs),
+
+    /// Export a household's recipes for Paprika, Mealie or Tandoor
+    ExportRecipes(ExportRecipesArgs),
+
+    /// Queue thumbnails for the videos in the reel directory that don't have one
+    ExtractThumbnails,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// Server address
+    #[clap(
+        short = 'a',
+        long = "address",
+        env = "RECIPE_ADDRESS",
+        default_value = "0.0.0.0:5005"
+    )]
+    pub address: SocketAddr,
+
+    /// Seconds between the reel downloads queued by a bulk import, so Instagram doesn't rate
+    /// limit us
+    #[clap(
+        long = "import-interval",
+        env = "RECIPE_IMPORT_INTERVAL",
+        default_value = "30"
+    )]
+    pub import_interval: u64,
+
+    /// Largest video, in megabytes, that can be uploaded directly
+    #[clap(
+        long = "max-video-upload-mb",
+        env = "RECIPE_MAX_VIDEO_UPLOAD_MB",
+        default_value = "200"
+    )]
+    pub max_video_upload_mb: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CreateUserArgs {
+    pub username: String,
+
+    #[clap(long = "password", env = "RECIPE_USER_PASSWORD")]
+    pub password: String,
+
+    /// Give this user every recipe and video imported before accounts existed
+    #[clap(long = "adopt-unowned")]
+    pub adopt_unowned: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ImportInstagramArgs {
+    /// The export's zip, or the folder it was extracted to
+    pub path: PathBuf,
+
+    /// User whose household the recipes are added to
+    #[clap(long = "user")]
+    pub username: String,
+
+    /// Seconds between the queued reel downloads
+    #[clap(
+        long = "import-interval",
+        env = "RECIPE_IMPORT_INTERVAL",
+        default_value = "30"
+    )]
+    pub import_interval: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ImportRecipesArgs {
+    /// A `.paprikarecipes` file, Mealie recipe JSON or zip, or Tandoor export zip
+    pub path: PathBuf,
+
+    /// User whose household the recipes are added to
+    #[clap(long = "user")]
+    pub username: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExportRecipesArgs {
+    /// User whose household's recipes are exported
+    #[clap(long = "user")]
+    pub username: String,
+
+    /// The app the export is for
+    #[clap(long = "app", value_enum)]
+    pub app: Manager,
+
+    /// File to write the export to
+    pub output: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct WorkerArgs {
+    /// Number of workers for each job type without a specific limit
+    #[clap(long = "workers", env = "RECIPE_WORKERS", default_value = "2")]
+    pub workers: u32,
+
+    /// Number of workers downloading reels
+    #[clap(long = "fetch-workers", env = "RECIPE_FETCH_WORKERS")]
+    pub fetch_workers: Option<u32>,
+
+    /// Number of workers transcribing videos
+    #[clap(long = "transcribe-workers", env = "RECIPE_TRANSCRIBE_WORKERS")]
+    pub transcribe_workers: Option<u32>,
+
+    /// Number of workers extracting recipes with the LLM
+    #[clap(long = "llm-workers", env = "RECIPE_LLM_WORKERS")]
+    pub llm_workers: Option<u32>,
+
+    /// Number of workers extracting thumbnails
+    #[clap(long = "thumbnail-workers", env = "RECIPE_THUMBNAIL_WORKERS")]
+    pub thumbnail_workers: Option<u32>,
+
+    /// Number of workers reading on-screen text from video frames
+    #[clap(long = "ocr-workers", env = "RECIPE_OCR_WORKERS")]
+    pub ocr_workers: Option<u32>,
+
+    /// Address to serve `/metrics` on when running workers without the web server
+    #[clap(long = "metrics-address", env = "RECIPE_METRICS_ADDRESS")]
+    pub metrics_address: Option<SocketAddr>,
 }
 
 impl Cli {
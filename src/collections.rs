@@ -0,0 +1,113 @@
+//! Named groups of recipes within a household. Imports from Instagram create one per saved
+//! collection and fill them in as the recipes get extracted.
+
+use crate::entities::prelude::Collections;
+use crate::entities::{collection_recipes, collections};
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbErr, EntityTrait, FromQueryResult, QueryFilter, QueryOrder,
+    QuerySelect, SelectModel, Selector, Statement,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The household's collection called `name`, creating it if need be.
+pub async fn find_or_create<C: ConnectionTrait>(
+    db: &C,
+    household_id: i32,
+    name: &str,
+) -> Result<collections::Model, DbErr> {
+    // `do update` rather than `do nothing` so the existing row is returned
+    collections::Model::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        insert into collections (household_id, name)
+values ($1, $2)
+on conflict (household_id, name) do update set name = excluded.name
+returning *;
+        "#,
+        vec![household_id.into(), name.into()],
+    ))
+    .one(db)
+    .await?
+    .ok_or_else(|| DbErr::RecordNotInserted)
+}
+
+/// Add the household's recipes to the collections their reels were imported into. Limited to
+/// one reel when called after its recipes were extracted, otherwise covers every import.
+pub async fn add_imported_recipes<C: ConnectionTrait>(
+    db: &C,
+    household_id: i32,
+    reel_id: Option<&str>,
+) -> Result<u64, DbErr> {
+    let result = db
+        .execute(Statement::from_sql_and_values(
+            Postgres,
+            r#"
+        insert into collection_recipes (collection_id, recipe_id)
+select ic.collection_id, r.id
+from import_item_collections ic
+         join import_items i on i.id = ic.import_item_id
+         join import_batches b on b.id = i.batch_id
+         join instagram_video iv on iv.instagram_id = i.reel_id
+         join recipes r on r.instagram_video_id = iv.id and r.household_id = b.household_id
+where b.household_id = $1
+  and ($2::text is null or i.reel_id = $2)
+on conflict do nothing;
+        "#,
+            vec![household_id.into(), reel_id.into()],
+        ))
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[derive(Serialize, FromQueryResult, ToSchema)]
+pub struct CollectionSummary {
+    pub id: i32,
+    pub name: String,
+    /// How many recipes are in it.
+    pub recipes: i64,
+}
+
+/// The household's collections by name, with how many recipes each holds.
+pub async fn list<C: ConnectionTrait>(
+    db: &C,
+    household_id: i32,
+) -> Result<Vec<CollectionSummary>, DbErr> {
+    summaries(household_id).all(db).await
+}
+
+/// The query behind [`list`], for the API to page through.
+pub fn summaries(household_id: i32) -> Selector<SelectModel<CollectionSummary>> {
+    Collections::find()
+        .select_only()
+        .column(collections::Column::Id)
+        .column(collections::Column::Name)
+        .column_as(collection_recipes::Column::RecipeId.count(), "recipes")
+        .left_join(crate::entities::prelude::CollectionRecipes)
+        .filter(collections::Column::HouseholdId.eq(household_id))
+        .group_by(collections::Column::Id)
+        .order_by_asc(collections::Column::Name)
+        .into_model::<CollectionSummary>()
+}
+
+/// Put a recipe in a collection, if it isn't already.
+pub async fn add_recipe<C: ConnectionTrait>(
+    db: &C,
+    collection_id: i32,
+    recipe_id: i32,
+) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        insert into collection_recipes (collection_id, recipe_id)
+values ($1, $2)
+on conflict do nothing;
+        "#,
+        vec![collection_id.into(), recipe_id.into()],
+    ))
+    .await?;
+
+    Ok(())
+}
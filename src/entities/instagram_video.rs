@@ -4,9 +4,11 @@ use crate::jobs::extract_transcript::Transcript;
 use crate::jobs::fetch_reel::ReelInfo;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, ToSchema)]
 #[sea_orm(table_name = "instagram_video")]
+#[schema(as = InstagramVideo)]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
@@ -20,12 +22,32 @@ pub struct Model {
     pub updated_at: Option<DateTime>,
     #[sea_orm(column_type = "JsonBinary", nullable)]
     pub transcript: Option<Transcript>,
+    pub owner_id: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub on_screen_text: Option<String>,
+    pub images: Option<Vec<String>>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::llm_extraction_failures::Entity")]
+    LlmExtractionFailures,
     #[sea_orm(has_many = "super::recipes::Entity")]
     Recipes,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::OwnerId",
+        to = "super::users::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Users,
+}
+
+impl Related<super::llm_extraction_failures::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LlmExtractionFailures.def()
+    }
 }
 
 impl Related<super::recipes::Entity> for Entity {
@@ -34,4 +56,10 @@ impl Related<super::recipes::Entity> for Entity {
     }
 }
 
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
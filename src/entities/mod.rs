@@ -2,5 +2,18 @@
 
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod collection_recipes;
+pub mod collections;
+pub mod household_invitations;
+pub mod household_members;
+pub mod households;
+pub mod import_batches;
+pub mod import_item_collections;
+pub mod import_items;
 pub mod instagram_video;
+pub mod llm_extraction_failures;
 pub mod recipes;
+pub mod sea_orm_active_enums;
+pub mod sessions;
+pub mod users;
@@ -1,4 +1,15 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.2
 
+pub use super::api_tokens::Entity as ApiTokens;
+pub use super::collection_recipes::Entity as CollectionRecipes;
+pub use super::collections::Entity as Collections;
+pub use super::household_invitations::Entity as HouseholdInvitations;
+pub use super::household_members::Entity as HouseholdMembers;
+pub use super::households::Entity as Households;
+pub use super::import_batches::Entity as ImportBatches;
+pub use super::import_items::Entity as ImportItems;
 pub use super::instagram_video::Entity as InstagramVideo;
+pub use super::llm_extraction_failures::Entity as LlmExtractionFailures;
 pub use super::recipes::Entity as Recipes;
+pub use super::sessions::Entity as Sessions;
+pub use super::users::Entity as Users;
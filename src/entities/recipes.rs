@@ -2,9 +2,11 @@
 
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, ToSchema)]
 #[sea_orm(table_name = "recipes")]
+#[schema(as = Recipe)]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
@@ -12,13 +14,37 @@ pub struct Model {
     pub title: Option<String>,
     pub ingredients: Option<Vec<String>>,
     pub instructions: Option<Vec<String>>,
+    #[schema(value_type = String, format = DateTime)]
     pub updated_at: DateTimeWithTimeZone,
     pub instagram_video_id: Option<i32>,
+    #[schema(value_type = Option<String>, format = DateTime)]
     pub generated_at: Option<DateTimeWithTimeZone>,
+    pub owner_id: Option<i32>,
+    pub household_id: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub servings: Option<String>,
+    pub prep_minutes: Option<i32>,
+    pub cook_minutes: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub notes: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub source_url: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub image: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::collection_recipes::Entity")]
+    CollectionRecipes,
+    #[sea_orm(
+        belongs_to = "super::households::Entity",
+        from = "Column::HouseholdId",
+        to = "super::households::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Households,
     #[sea_orm(
         belongs_to = "super::instagram_video::Entity",
         from = "Column::InstagramVideoId",
@@ -27,6 +53,26 @@ pub enum Relation {
         on_delete = "NoAction"
     )]
     InstagramVideo,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::OwnerId",
+        to = "super::users::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Users,
+}
+
+impl Related<super::collection_recipes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CollectionRecipes.def()
+    }
+}
+
+impl Related<super::households::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Households.def()
+    }
 }
 
 impl Related<super::instagram_video::Entity> for Entity {
@@ -35,4 +81,19 @@ impl Related<super::instagram_video::Entity> for Entity {
     }
 }
 
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl Related<super::collections::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::collection_recipes::Relation::Collections.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::collection_recipes::Relation::Recipes.def().rev())
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,20 +1,34 @@
+use crate::negotiate;
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use axum::http::StatusCode;
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
+use axum::Json;
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
 use fang::{AsyncQueueError, FangError};
+use minijinja_autoreload::AutoReloader;
 use sea_orm::DbErr;
-use tracing::log::error;
+use serde::Serialize;
+use serde_json::json;
+use utoipa::ToSchema;
 
 pub type Result<T, E = AppError> = std::result::Result<T, E>;
 
+pub const PROBLEM_JSON: &str = "application/problem+json";
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     /// SeaORM error, separated for ease of use allowing us to `?` db operations.
     #[error("Internal error")]
-    DbError(#[from] DbErr),
+    DbError(DbErr),
 
     /// Fang error
     #[error("Job scheduler error {0}")]
-    JobSchedulerError(#[from] AsyncQueueError),
+    JobSchedulerError(AsyncQueueError),
 
     #[error("Job error {0}")]
     FangError(String),
@@ -22,6 +36,37 @@ pub enum AppError {
     #[error("Invalid request {0}")]
     BadRequest(anyhow::Error),
 
+    /// The user isn't signed in, or their session or API token is invalid.
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    /// The user is signed in but their household role doesn't allow this.
+    #[error("Forbidden")]
+    Forbidden,
+
+    /// The thing being asked for doesn't exist, or isn't visible to this user.
+    #[error("{0} not found")]
+    NotFound(&'static str),
+
+    /// The request is valid but clashes with the current state, e.g. removing the last owner.
+    #[error("{0}")]
+    Conflict(anyhow::Error),
+
+    /// An upload bigger than we accept.
+    #[error("Upload is larger than the {limit_mb} MB limit")]
+    PayloadTooLarge { limit_mb: u64 },
+
+    /// An upload whose contents aren't a format we handle, whatever it claims to be.
+    #[error("Unsupported file type, expected {expected}")]
+    UnsupportedMediaType { expected: &'static str },
+
+    /// Something we depend on (the database, the job queue) can't be reached right now.
+    #[error("{service} is unavailable")]
+    UpstreamUnavailable {
+        service: &'static str,
+        error: anyhow::Error,
+    },
+
     /// Catch all for error we don't care to expose publicly.
     #[error("Internal error")]
     Anyhow(#[from] anyhow::Error),
@@ -33,15 +78,190 @@ impl From<FangError> for AppError {
     }
 }
 
+impl From<DbErr> for AppError {
+    fn from(err: DbErr) -> Self {
+        match err {
+            DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => AppError::UpstreamUnavailable {
+                service: "Database",
+                error: err.into(),
+            },
+            err => AppError::DbError(err),
+        }
+    }
+}
+
+impl From<AsyncQueueError> for AppError {
+    fn from(err: AsyncQueueError) -> Self {
+        match err {
+            AsyncQueueError::PoolError(_) | AsyncQueueError::NotConnectedError => {
+                AppError::UpstreamUnavailable {
+                    service: "Job queue",
+                    error: err.into(),
+                }
+            }
+            err => AppError::JobSchedulerError(err),
+        }
+    }
+}
+
+impl AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::UpstreamUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::DbError(_)
+            | AppError::JobSchedulerError(_)
+            | AppError::FangError(_)
+            | AppError::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// RFC 7807 problem details. `AppError` leaves one in the response extensions and
+/// [`render_errors`] turns it into JSON or an HTML page once it knows what the client accepts.
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    /// Matches the `x-request-id` response header and the `request_id` in our logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl Problem {
+    fn new(status_code: StatusCode, detail: Option<String>) -> Self {
+        Problem {
+            kind: "about:blank",
+            title: status_code
+                .canonical_reason()
+                .unwrap_or("Error")
+                .to_string(),
+            status: status_code.as_u16(),
+            detail,
+            instance: None,
+            request_id: None,
+        }
+    }
+
+    /// Whether `response` is an error that didn't come from [`AppError`]: axum's extractor
+    /// rejections and routing errors, which are plain text or empty.
+    fn is_plain_error(response: &Response) -> bool {
+        let status = response.status();
+        let is_plain_text = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|hv| hv.to_str().ok())
+            .is_none_or(|content_type| content_type.starts_with("text/plain"));
+
+        (status.is_client_error() || status.is_server_error()) && is_plain_text
+    }
+
+    /// The problem for a plain error response, with its text as the detail, and the response
+    /// with the text taken out.
+    async fn from_plain_error(response: Response) -> (Self, Response) {
+        let status = response.status();
+        let (parts, body) = response.into_parts();
+        let text = axum::body::to_bytes(body, MAX_PLAIN_ERROR_BYTES)
+            .await
+            .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+            .unwrap_or_default();
+
+        let detail = (status.is_client_error() && !text.is_empty()).then_some(text);
+        (
+            Problem::new(status, detail),
+            Response::from_parts(parts, Body::empty()),
+        )
+    }
+}
+
+/// Longest plain text error body read into a problem's `detail`, rejections are a line or two.
+const MAX_PLAIN_ERROR_BYTES: usize = 64 * 1024;
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        error!("Internal server error: {self:?}");
+        let status_code = self.status_code();
 
-        let status_code = match self {
-            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        // Internal details only go to the logs, the request id ties the two together
+        let detail = if status_code.is_server_error() {
+            tracing::error!("Internal server error: {self:?}");
+            None
+        } else {
+            tracing::info!("Request failed: {self}");
+            Some(self.to_string())
         };
 
-        (status_code, self.to_string()).into_response()
+        let problem = Problem::new(status_code, detail);
+
+        let mut response = (status_code, self.to_string()).into_response();
+        response.extensions_mut().insert(problem);
+        response
     }
 }
+
+/// Fallback for requests that match no route.
+pub async fn not_found() -> AppError {
+    AppError::NotFound("Page")
+}
+
+/// Render errors from [`AppError`], and axum's own plain text ones, as `application/problem+json`
+/// for API clients and as an error page for browsers. Anything under `/api` is always JSON.
+///
+/// Takes the template engine as state because it runs outside the `Extension` layers.
+pub async fn render_errors(
+    State(template_engine): State<Engine<AutoReloader>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let wants_json = request.uri().path().starts_with("/api")
+        || negotiate::preferred(
+            request.headers(),
+            &[negotiate::HTML, PROBLEM_JSON, negotiate::JSON],
+        ) != Some(negotiate::HTML);
+
+    let instance = request.uri().path().to_string();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|hv| hv.to_str().ok())
+        .map(str::to_string);
+
+    let mut response = next.run(request).await;
+
+    let (mut problem, response) = match response.extensions_mut().remove::<Problem>() {
+        Some(problem) => (problem, response),
+        None if Problem::is_plain_error(&response) => Problem::from_plain_error(response).await,
+        None => return response,
+    };
+
+    problem.instance = Some(instance);
+    problem.request_id = request_id;
+
+    let status = response.status();
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let body = if wants_json {
+        ([(CONTENT_TYPE, PROBLEM_JSON)], Json(problem)).into_response()
+    } else {
+        RenderHtml("errors/show.html", template_engine, json!(problem)).into_response()
+    };
+
+    let (body_parts, body) = body.into_parts();
+    parts.headers.extend(body_parts.headers);
+    parts.status = status;
+
+    Response::from_parts(parts, body)
+}
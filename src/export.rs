@@ -0,0 +1,686 @@
+//! Recipes as plain files, for keeping them in a notes app or a git repo: Markdown, Cooklang and
+//! plain text, one recipe at a time or the whole library as a zip.
+//!
+//! Ingredients are stored as the lines the LLM wrote, so for Cooklang each line is split into a
+//! quantity, a unit and a name, and the name is looked for in the steps to mark it inline.
+//! Ingredients that no step mentions are kept in a comment rather than dropped.
+
+use crate::auth::CurrentUser;
+use crate::entities::prelude::{InstagramVideo, Recipes};
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::entities::{instagram_video, recipes};
+use crate::error::{self, AppError};
+use crate::uploads;
+use axum::extract::Query;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use regex::Regex;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use utoipa::IntoParams;
+
+/// Words that come between the quantity and the name of an ingredient.
+const UNITS: &[&str] = &[
+    "g",
+    "gram",
+    "grams",
+    "kg",
+    "mg",
+    "ml",
+    "l",
+    "cl",
+    "dl",
+    "litre",
+    "litres",
+    "liter",
+    "liters",
+    "cup",
+    "cups",
+    "tbsp",
+    "tbs",
+    "tablespoon",
+    "tablespoons",
+    "tsp",
+    "teaspoon",
+    "teaspoons",
+    "oz",
+    "ounce",
+    "ounces",
+    "lb",
+    "lbs",
+    "pound",
+    "pounds",
+    "pinch",
+    "pinches",
+    "dash",
+    "dashes",
+    "clove",
+    "cloves",
+    "can",
+    "cans",
+    "tin",
+    "tins",
+    "slice",
+    "slices",
+    "handful",
+    "handfuls",
+    "bunch",
+    "bunches",
+    "sprig",
+    "sprigs",
+    "stick",
+    "sticks",
+    "piece",
+    "pieces",
+];
+
+const FRACTIONS: &[(char, &str)] = &[
+    ('½', "1/2"),
+    ('⅓', "1/3"),
+    ('⅔', "2/3"),
+    ('¼', "1/4"),
+    ('¾', "3/4"),
+    ('⅛', "1/8"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Cooklang,
+    Text,
+}
+
+impl Format {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "md" => Some(Format::Markdown),
+            "cook" => Some(Format::Cooklang),
+            "txt" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Cooklang => "cook",
+            Format::Text => "txt",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Markdown => "text/markdown; charset=utf-8",
+            Format::Cooklang | Format::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// A recipe as the text of a file in `format`, `video_url` is the reel it came from if any.
+pub fn render(recipe: &recipes::Model, video_url: Option<&str>, format: Format) -> String {
+    let title = recipe.title.as_deref().unwrap_or("Untitled recipe");
+    let ingredients = recipe.ingredients.as_deref().unwrap_or_default();
+    let instructions = recipe.instructions.as_deref().unwrap_or_default();
+    let notes = recipe.notes.as_deref();
+    let source = recipe.source_url.as_deref().or(video_url);
+
+    match format {
+        Format::Markdown => markdown(title, ingredients, instructions, notes, source),
+        Format::Cooklang => cooklang(title, ingredients, instructions, notes, source),
+        Format::Text => text(title, ingredients, instructions, notes, source),
+    }
+}
+
+fn markdown(
+    title: &str,
+    ingredients: &[String],
+    instructions: &[String],
+    notes: Option<&str>,
+    source: Option<&str>,
+) -> String {
+    let mut out = format!("# {title}\n\n## Ingredients\n\n");
+
+    for ingredient in ingredients {
+        out.push_str(&format!("- {}\n", one_line(ingredient)));
+    }
+
+    out.push_str("\n## Instructions\n\n");
+    for (i, step) in instructions.iter().enumerate() {
+        out.push_str(&format!("{}. {}\n", i + 1, one_line(step)));
+    }
+
+    if let Some(notes) = notes {
+        out.push_str(&format!("\n## Notes\n\n{}\n", notes.trim()));
+    }
+
+    if let Some(source) = source {
+        out.push_str(&format!("\n[Source]({source})\n"));
+    }
+
+    out
+}
+
+fn text(
+    title: &str,
+    ingredients: &[String],
+    instructions: &[String],
+    notes: Option<&str>,
+    source: Option<&str>,
+) -> String {
+    let mut out = format!(
+        "{title}\n{}\n\nIngredients\n\n",
+        "=".repeat(title.chars().count())
+    );
+
+    for ingredient in ingredients {
+        out.push_str(&format!("- {}\n", one_line(ingredient)));
+    }
+
+    out.push_str("\nInstructions\n\n");
+    for (i, step) in instructions.iter().enumerate() {
+        out.push_str(&format!("{}. {}\n", i + 1, one_line(step)));
+    }
+
+    if let Some(notes) = notes {
+        out.push_str(&format!("\nNotes\n\n{}\n", notes.trim()));
+    }
+
+    if let Some(source) = source {
+        out.push_str(&format!("\nSource: {source}\n"));
+    }
+
+    out
+}
+
+fn cooklang(
+    title: &str,
+    ingredients: &[String],
+    instructions: &[String],
+    notes: Option<&str>,
+    source: Option<&str>,
+) -> String {
+    let mut out = format!(">> title: {}\n", one_line(title));
+
+    if let Some(source) = source {
+        out.push_str(&format!(">> source: {source}\n"));
+    }
+
+    let steps: Vec<String> = instructions.iter().map(|step| one_line(step)).collect();
+
+    // Where each ingredient is marked, as (step, start, end, amount), first mention only
+    let mut marks: Vec<(usize, usize, usize, String)> = vec![];
+    let mut unmarked = vec![];
+
+    for ingredient in ingredients {
+        let parsed = Ingredient::parse(ingredient);
+        let mut found_any = false;
+
+        for name in &parsed.names {
+            let found = candidates(name).into_iter().find_map(|pattern| {
+                steps.iter().enumerate().find_map(|(step, text)| {
+                    pattern
+                        .find_iter(text)
+                        .find(|m| {
+                            !marks.iter().any(|(s, start, end, _)| {
+                                *s == step && m.start() < *end && *start < m.end()
+                            })
+                        })
+                        .map(|m| (step, m.start(), m.end()))
+                })
+            });
+
+            if let Some((step, start, end)) = found {
+                marks.push((step, start, end, parsed.amount()));
+                found_any = true;
+            }
+        }
+
+        if !found_any {
+            unmarked.push(ingredient);
+        }
+    }
+
+    if !unmarked.is_empty() {
+        out.push_str("\n-- Not mentioned in the steps:\n");
+        for ingredient in unmarked {
+            out.push_str(&format!("-- {}\n", one_line(ingredient)));
+        }
+    }
+
+    for (step, text) in steps.iter().enumerate() {
+        let mut step_marks: Vec<_> = marks.iter().filter(|mark| mark.0 == step).collect();
+        step_marks.sort_by_key(|mark| mark.1);
+
+        let mut annotated = String::new();
+        let mut cursor = 0;
+
+        for &(_, start, end, ref amount) in step_marks {
+            annotated.push_str(&text[cursor..start]);
+            annotated.push_str(&format!("@{}{{{amount}}}", &text[start..end]));
+            cursor = end;
+        }
+
+        annotated.push_str(&text[cursor..]);
+        out.push_str(&format!("\n{annotated}\n"));
+    }
+
+    // Cooklang has nowhere for free text outside the steps, so notes go in as comments
+    if let Some(notes) = notes {
+        out.push('\n');
+        for line in notes.trim().lines() {
+            out.push_str(&format!("-- {line}\n"));
+        }
+    }
+
+    out
+}
+
+/// Steps and ingredients written over several lines would otherwise break the list or paragraph.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// An ingredient line split up, e.g. `200g spaghetti` or `2 cloves garlic, minced`.
+#[derive(Debug, Default)]
+pub(crate) struct Ingredient {
+    pub(crate) quantity: Option<String>,
+    pub(crate) unit: Option<String>,
+    /// Usually one, "salt and pepper" with no quantity is two.
+    pub(crate) names: Vec<String>,
+}
+
+impl Ingredient {
+    pub(crate) fn parse(line: &str) -> Self {
+        let mut ingredient = Ingredient::default();
+
+        // Preparation notes follow a comma or are in brackets, neither is part of the name
+        let line = line.split([',', '(']).next().unwrap_or_default();
+        let mut words = line.split_whitespace().peekable();
+
+        let mut quantity = vec![];
+        while let Some(word) = words.next_if(|word| is_quantity(word)) {
+            quantity.push(normalise_fractions(word));
+        }
+
+        // A unit written straight after the number, `200g`
+        if quantity.is_empty() {
+            if let Some((number, unit)) = words.peek().and_then(|word| split_attached_unit(word)) {
+                quantity.push(number);
+                ingredient.unit = Some(unit);
+                words.next();
+            }
+        }
+
+        if !quantity.is_empty() {
+            ingredient.quantity = Some(quantity.join(" "));
+        }
+
+        if ingredient.unit.is_none() {
+            ingredient.unit = words
+                .next_if(|word| UNITS.contains(&unit_key(word).as_str()))
+                .map(|word| word.trim_end_matches('.').to_string());
+        }
+
+        words.next_if(|word| word.eq_ignore_ascii_case("of"));
+
+        let name = words.collect::<Vec<_>>().join(" ").to_lowercase();
+        let name = [" to taste", " for "]
+            .iter()
+            .fold(name.as_str(), |name, cut| {
+                name.split(cut).next().unwrap_or(name)
+            })
+            .trim()
+            .to_string();
+
+        ingredient.names = if ingredient.quantity.is_none() {
+            name.split(" and ")
+                .map(str::trim)
+                .map(str::to_string)
+                .collect()
+        } else {
+            vec![name]
+        };
+        ingredient.names.retain(|name| !name.is_empty());
+
+        ingredient
+    }
+
+    /// The quantity as a number, the lower end of a range like `1-2`.
+    pub(crate) fn amount_value(&self) -> Option<f64> {
+        self.quantity
+            .as_deref()?
+            .split_whitespace()
+            .map(|part| {
+                let part = part.split('-').next().unwrap_or(part);
+                match part.split_once('/') {
+                    Some((numerator, denominator)) => {
+                        Some(numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?)
+                    }
+                    None => part.parse().ok(),
+                }
+            })
+            .sum()
+    }
+
+    /// The Cooklang amount, the part between the braces.
+    fn amount(&self) -> String {
+        match (&self.quantity, &self.unit) {
+            (Some(quantity), Some(unit)) => format!("{quantity}%{unit}"),
+            (Some(quantity), None) => quantity.clone(),
+            (None, _) => String::new(),
+        }
+    }
+}
+
+fn is_quantity(word: &str) -> bool {
+    word.chars()
+        .any(|c| c.is_ascii_digit() || FRACTIONS.iter().any(|(f, _)| *f == c))
+        && word.chars().all(|c| {
+            c.is_ascii_digit() || "./-".contains(c) || FRACTIONS.iter().any(|(f, _)| *f == c)
+        })
+}
+
+fn split_attached_unit(word: &str) -> Option<(String, String)> {
+    let split = word.find(|c: char| c.is_alphabetic())?;
+    let (number, unit) = word.split_at(split);
+
+    (is_quantity(number) && UNITS.contains(&unit_key(unit).as_str())).then(|| {
+        (
+            normalise_fractions(number),
+            unit.trim_end_matches('.').to_string(),
+        )
+    })
+}
+
+fn unit_key(word: &str) -> String {
+    word.trim_end_matches('.').to_lowercase()
+}
+
+/// Cooklang only understands ASCII fractions.
+fn normalise_fractions(word: &str) -> String {
+    let mut out = String::new();
+
+    for c in word.chars() {
+        match FRACTIONS.iter().find(|(f, _)| *f == c) {
+            Some((_, ascii)) if out.is_empty() => out.push_str(ascii),
+            Some((_, ascii)) => out.push_str(&format!(" {ascii}")),
+            None => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Patterns to find `name` in a step with, most specific first: the whole name, then shorter
+/// endings of it so "extra virgin olive oil" still matches "the olive oil". Plurals match either
+/// way round.
+fn candidates(name: &str) -> Vec<Regex> {
+    let words: Vec<&str> = name.split_whitespace().collect();
+
+    (0..words.len())
+        .map(|skip| &words[skip..])
+        .filter(|words| words.len() > 1 || words[0].chars().count() >= 3)
+        .filter_map(|words| {
+            let (last, rest) = words.split_last()?;
+            let stem = last
+                .strip_suffix("oes")
+                .map(|stem| format!("{stem}o"))
+                .or_else(|| {
+                    last.strip_suffix('s')
+                        .filter(|_| !last.ends_with("ss"))
+                        .map(str::to_string)
+                })
+                .unwrap_or_else(|| last.to_string());
+
+            let mut pattern: Vec<String> = rest.iter().map(|word| regex::escape(word)).collect();
+            pattern.push(format!("{}(?:es|s)?", regex::escape(&stem)));
+
+            Regex::new(&format!(r"(?i)\b{}\b", pattern.join(r"\s+"))).ok()
+        })
+        .collect()
+}
+
+/// A recipe in the household and the video it came from, if any.
+pub(crate) async fn load_recipe(
+    db: &DatabaseConnection,
+    household_id: i32,
+    recipe_id: i32,
+) -> error::Result<(recipes::Model, Option<instagram_video::Model>)> {
+    Recipes::find_by_id(recipe_id)
+        .filter(recipes::Column::HouseholdId.eq(household_id))
+        .find_also_related(InstagramVideo)
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Recipe"))
+}
+
+/// `text` in lowercase with dashes, for file names.
+pub(crate) fn slugify(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// File name for a recipe, its title in lowercase with dashes.
+pub(crate) fn slug(recipe: &recipes::Model) -> String {
+    let slug = slugify(recipe.title.as_deref().unwrap_or_default());
+
+    if slug.is_empty() {
+        format!("recipe-{}", recipe.id)
+    } else {
+        slug
+    }
+}
+
+/// One recipe as a file, served for `/recipes/{id}.md`, `.cook` and `.txt`.
+pub async fn export_recipe(
+    db: &DatabaseConnection,
+    household_id: i32,
+    recipe_id: i32,
+    format: Format,
+) -> error::Result<Response> {
+    let (recipe, video) = load_recipe(db, household_id, recipe_id).await?;
+
+    let source = video.as_ref().and_then(uploads::source_url);
+    let disposition = format!(
+        "inline; filename=\"{}.{}\"",
+        slug(&recipe),
+        format.extension()
+    );
+
+    Ok((
+        [
+            (CONTENT_TYPE, format.content_type().to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        render(&recipe, source, format),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportLibraryQuery {
+    /// `md`, `cook` or `txt`, Markdown if not given.
+    format: Option<String>,
+}
+
+/// Every recipe in the household as a zip of files in one format.
+#[utoipa::path(
+    get,
+    path = "/recipes/export.zip",
+    tag = "recipes",
+    params(ExportLibraryQuery),
+    responses(
+        (status = 200, description = "The library", content_type = "application/zip"),
+        (status = 400, description = "Unknown format"),
+    )
+)]
+pub async fn export_library(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Query(query): Query<ExportLibraryQuery>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let format = match query.format.as_deref() {
+        None => Format::Markdown,
+        Some(extension) => Format::from_extension(extension).ok_or_else(|| {
+            AppError::BadRequest(anyhow::anyhow!("Unknown export format {extension}"))
+        })?,
+    };
+
+    let recipes = Recipes::find()
+        .filter(recipes::Column::HouseholdId.eq(user.household_id))
+        .find_also_related(InstagramVideo)
+        .all(&db)
+        .await?;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default();
+    let mut names = HashSet::new();
+
+    for (recipe, video) in &recipes {
+        // Titles aren't unique, the id tells two "Pasta"s apart
+        let mut name = slug(recipe);
+        if !names.insert(name.clone()) {
+            name = format!("{name}-{}", recipe.id);
+            names.insert(name.clone());
+        }
+
+        let source = video.as_ref().and_then(uploads::source_url);
+
+        zip.start_file(format!("{name}.{}", format.extension()), options)
+            .map_err(anyhow::Error::from)?;
+        zip.write_all(render(recipe, source, format).as_bytes())
+            .map_err(anyhow::Error::from)?;
+    }
+
+    let bytes = zip.finish().map_err(anyhow::Error::from)?.into_inner();
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"recipes-{}.zip\"",
+                    format.extension()
+                ),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(line: &str) -> (Option<String>, Option<String>, Vec<String>) {
+        let ingredient = Ingredient::parse(line);
+        (ingredient.quantity, ingredient.unit, ingredient.names)
+    }
+
+    fn some(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[test]
+    fn parses_attached_units() {
+        assert_eq!(
+            parsed("200g spaghetti"),
+            (some("200"), some("g"), vec!["spaghetti".to_string()])
+        );
+    }
+
+    #[test]
+    fn parses_unicode_fractions() {
+        assert_eq!(
+            parsed("½ cup milk"),
+            (some("1/2"), some("cup"), vec!["milk".to_string()])
+        );
+        assert_eq!(Ingredient::parse("1½ cups flour").amount_value(), Some(1.5));
+    }
+
+    #[test]
+    fn drops_preparation_notes() {
+        assert_eq!(
+            parsed("2 cloves of garlic, minced"),
+            (some("2"), some("cloves"), vec!["garlic".to_string()])
+        );
+        assert_eq!(
+            parsed("1 tbsp. olive oil for frying"),
+            (some("1"), some("tbsp"), vec!["olive oil".to_string()])
+        );
+    }
+
+    #[test]
+    fn splits_unquantified_pairs() {
+        assert_eq!(
+            parsed("Salt and pepper to taste"),
+            (None, None, vec!["salt".to_string(), "pepper".to_string()])
+        );
+        assert_eq!(
+            parsed("1 tin tomatoes and basil"),
+            (
+                some("1"),
+                some("tin"),
+                vec!["tomatoes and basil".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn ranges_take_the_lower_end() {
+        assert_eq!(Ingredient::parse("1-2 onions").amount_value(), Some(1.0));
+        assert_eq!(Ingredient::parse("a pinch of salt").amount_value(), None);
+    }
+
+    #[test]
+    fn normalises_fractions() {
+        assert_eq!(normalise_fractions("½"), "1/2");
+        assert_eq!(normalise_fractions("1½"), "1 1/2");
+        assert_eq!(normalise_fractions("2"), "2");
+    }
+
+    #[test]
+    fn candidates_match_plurals_either_way() {
+        let matches = |name: &str, text: &str| {
+            candidates(name)
+                .iter()
+                .find_map(|pattern| pattern.find(text))
+                .map(|m| m.as_str().to_string())
+        };
+
+        assert_eq!(matches("onion", "Slice the onions"), some("onions"));
+        assert_eq!(matches("tomatoes", "Add the tomato"), some("tomato"));
+        assert_eq!(matches("potatoes", "Peel the Potatoes"), some("Potatoes"));
+        assert_eq!(matches("cress", "Top with cress"), some("cress"));
+        assert_eq!(
+            matches("extra virgin olive oil", "Heat the olive oil"),
+            some("olive oil")
+        );
+        assert_eq!(matches("egg", "Add the eggplant"), None);
+    }
+
+    #[test]
+    fn cooklang_marks_first_mentions() {
+        let ingredients =
+            ["200g spaghetti", "2 tomatoes", "salt and pepper", "1 lemon"].map(str::to_string);
+        let instructions =
+            ["Boil the spaghetti", "Chop the tomato, season with salt"].map(str::to_string);
+
+        assert_eq!(
+            cooklang("Pasta", &ingredients, &instructions, None, None),
+            ">> title: Pasta\n\
+             \n-- Not mentioned in the steps:\n-- 1 lemon\n\
+             \nBoil the @spaghetti{200%g}\n\
+             \nChop the @tomato{2}, season with @salt{}\n"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::jobs::JobContext;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{ConnectionTrait, Statement};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::time::Duration;
+use tokio::process::Command;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Serialize)]
+struct Check {
+    ok: bool,
+    detail: String,
+}
+
+impl Check {
+    fn from_result(result: anyhow::Result<String>) -> Self {
+        match result {
+            Ok(detail) => Check { ok: true, detail },
+            Err(err) => Check {
+                ok: false,
+                detail: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+/// Liveness, the process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "ops",
+    security(()),
+    responses((status = 200, example = json!({ "status": "ok" })))
+)]
+pub async fn health() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness, every dependency the pipeline relies on is reachable.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "ops",
+    security(()),
+    responses(
+        (status = 200, description = "Every check passed", example = json!({
+            "status": "ok",
+            "checks": { "database": { "ok": true, "detail": "PostgreSQL 16.2" } },
+        })),
+        (status = 503, description = "At least one check failed, see `checks`"),
+    )
+)]
+pub async fn ready(Extension(context): Extension<JobContext>) -> impl IntoResponse {
+    let (
+        database,
+        migrations,
+        reel_dir,
+        yt_dlp,
+        gallery_dl,
+        ffmpeg,
+        tesseract,
+        whisper,
+        completion,
+    ) = tokio::join!(
+        check_database(&context),
+        check_migrations(&context),
+        check_reel_dir(&context),
+        check_binary(&context.yt_dlp_command_string, "--version"),
+        check_binary(&context.gallery_dl_command_string, "--version"),
+        check_binary(OsStr::new("ffmpeg"), "-version"),
+        check_binary(OsStr::new("tesseract"), "--version"),
+        check_url(&context.whisper_url),
+        check_url(&context.completion_url),
+    );
+
+    let checks = BTreeMap::from([
+        ("database", Check::from_result(database)),
+        ("migrations", Check::from_result(migrations)),
+        ("reel_dir", Check::from_result(reel_dir)),
+        ("yt_dlp", Check::from_result(yt_dlp)),
+        ("gallery_dl", Check::from_result(gallery_dl)),
+        ("ffmpeg", Check::from_result(ffmpeg)),
+        ("tesseract", Check::from_result(tesseract)),
+        ("whisper", Check::from_result(whisper)),
+        ("completion", Check::from_result(completion)),
+    ]);
+
+    let ok = checks.values().all(|check| check.ok);
+    let status_code = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        Json(json!({
+            "status": if ok { "ok" } else { "error" },
+            "checks": checks,
+        })),
+    )
+}
+
+async fn check_database(context: &JobContext) -> anyhow::Result<String> {
+    let row = context
+        .db
+        .query_one(Statement::from_string(Postgres, "select version()"))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No version returned"))?;
+
+    Ok(row.try_get("", "version")?)
+}
+
+async fn check_migrations(context: &JobContext) -> anyhow::Result<String> {
+    let applied = context
+        .db
+        .query_all(Statement::from_string(
+            Postgres,
+            "select version from _sqlx_migrations where success order by version",
+        ))
+        .await?
+        .iter()
+        .map(|row| row.try_get::<i64>("", "version"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let pending = sqlx::migrate!()
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| migration.version.to_string())
+        .collect::<Vec<_>>();
+
+    if !pending.is_empty() {
+        anyhow::bail!("Pending migrations: {}", pending.join(", "));
+    }
+
+    Ok(format!("{} migrations applied", applied.len()))
+}
+
+async fn check_reel_dir(context: &JobContext) -> anyhow::Result<String> {
+    tempfile::NamedTempFile::new_in(&context.reel_dir)?;
+    Ok(format!("{} is writable", context.reel_dir.display()))
+}
+
+async fn check_binary(command: &OsStr, version_flag: &str) -> anyhow::Result<String> {
+    let output = tokio::time::timeout(
+        CHECK_TIMEOUT,
+        Command::new(command).arg(version_flag).output(),
+    )
+    .await??;
+
+    if !output.status.success() {
+        anyhow::bail!("{:?} exited with {}", command, output.status);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+/// Any HTTP response counts as reachable, we only care that something is listening.
+async fn check_url(url: &str) -> anyhow::Result<String> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .await?;
+
+    Ok(format!("{url} responded with {}", response.status()))
+}
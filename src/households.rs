@@ -0,0 +1,488 @@
+use crate::auth::{self, CurrentUser};
+use crate::entities::prelude::{
+    Collections, HouseholdInvitations, HouseholdMembers, Households, ImportBatches,
+    LlmExtractionFailures,
+};
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::entities::{
+    collections, household_invitations, household_members, households, import_batches,
+    llm_extraction_failures, recipes, users,
+};
+use crate::error::{self, AppError};
+use anyhow::anyhow;
+use axum::extract::Path;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use minijinja_autoreload::AutoReloader;
+use sea_orm::sea_query::{Expr, Query as SeaQuery};
+use sea_orm::{
+    ActiveEnum, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    FromQueryResult, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+const INVITATION_DAYS: i64 = 7;
+
+/// Give a new user a household of their own, which they own.
+pub async fn create_for<C: ConnectionTrait>(
+    db: &C,
+    user: &users::Model,
+) -> anyhow::Result<households::Model> {
+    let household = households::ActiveModel {
+        name: Set(format!("{}'s household", user.username)),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    household_members::ActiveModel {
+        household_id: Set(household.id),
+        user_id: Set(user.id),
+        role: Set(HouseholdRole::Owner),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(household)
+}
+
+#[derive(Serialize, FromQueryResult)]
+struct Member {
+    user_id: i32,
+    username: String,
+    role: HouseholdRole,
+}
+
+async fn render_household(
+    user: &CurrentUser,
+    db: &DatabaseConnection,
+    template_engine: Engine<AutoReloader>,
+    invite_path: Option<String>,
+) -> error::Result<Response> {
+    let household = Households::find_by_id(user.household_id)
+        .one(db)
+        .await?
+        .ok_or(anyhow!("Household not found"))?;
+
+    let members = HouseholdMembers::find()
+        .select_only()
+        .column(household_members::Column::UserId)
+        .column(users::Column::Username)
+        .column(household_members::Column::Role)
+        .inner_join(crate::entities::prelude::Users)
+        .filter(household_members::Column::HouseholdId.eq(user.household_id))
+        .order_by_asc(users::Column::Username)
+        .into_model::<Member>()
+        .all(db)
+        .await?;
+
+    let invitations = HouseholdInvitations::find()
+        .filter(household_invitations::Column::HouseholdId.eq(user.household_id))
+        .filter(household_invitations::Column::AcceptedAt.is_null())
+        .filter(household_invitations::Column::ExpiresAt.gt(chrono::Utc::now()))
+        .order_by_asc(household_invitations::Column::ExpiresAt)
+        .all(db)
+        .await?;
+
+    Ok(RenderHtml(
+        "households/show.html",
+        template_engine,
+        json!({
+            "household": household,
+            "members": members,
+            "invitations": invitations,
+            "invite_path": invite_path,
+            "user": {
+                "id": user.id,
+                "username": user.username,
+                "role": user.role,
+                "is_owner": user.role == HouseholdRole::Owner,
+            },
+        }),
+    )
+    .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/household",
+    tag = "household",
+    responses((status = 200, content_type = "text/html"))
+)]
+pub async fn show_household(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> error::Result<Response> {
+    render_household(&user, &db, template_engine, None).await
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateInvitationRequest {
+    #[schema(value_type = String, example = "editor")]
+    role: HouseholdRole,
+}
+
+/// The link is only shown once as we only keep its hash.
+#[utoipa::path(
+    post,
+    path = "/household/invitations",
+    tag = "household",
+    request_body(content_type = "application/x-www-form-urlencoded", content = inline(CreateInvitationRequest)),
+    responses(
+        (status = 200, description = "The household page showing the new link", content_type = "text/html"),
+        (status = 403, description = "Only owners can invite"),
+    )
+)]
+pub async fn create_invitation(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(request): Form<CreateInvitationRequest>,
+) -> error::Result<Response> {
+    user.require(HouseholdRole::Owner)?;
+
+    let token = auth::generate_token();
+    let now = chrono::Utc::now();
+
+    household_invitations::ActiveModel {
+        token_hash: Set(auth::hash_token(&token)),
+        household_id: Set(user.household_id),
+        role: Set(request.role),
+        created_by: Set(user.id),
+        created_at: Set(now.fixed_offset()),
+        expires_at: Set((now + chrono::Duration::days(INVITATION_DAYS)).fixed_offset()),
+
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+
+    render_household(
+        &user,
+        &db,
+        template_engine,
+        Some(format!("/invitations/{token}")),
+    )
+    .await
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct UpdateMemberRequest {
+    #[schema(value_type = String, example = "viewer")]
+    role: HouseholdRole,
+}
+
+#[utoipa::path(
+    post,
+    path = "/household/members/{user_id}",
+    tag = "household",
+    params(("user_id" = i32, Path)),
+    request_body(content_type = "application/x-www-form-urlencoded", content = inline(UpdateMemberRequest)),
+    responses(
+        (status = 303, description = "Role changed"),
+        (status = 409, description = "The household would be left without an owner"),
+        (status = 403, description = "Only owners can change roles"),
+        (status = 404, description = "Not a member of the household"),
+    )
+)]
+pub async fn update_member(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((member_id,)): Path<(i32,)>,
+    Form(request): Form<UpdateMemberRequest>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Owner)?;
+
+    let txn = db.begin().await?;
+
+    let updated = HouseholdMembers::update_many()
+        .col_expr(household_members::Column::Role, request.role.as_enum())
+        .filter(household_members::Column::HouseholdId.eq(user.household_id))
+        .filter(household_members::Column::UserId.eq(member_id))
+        .exec(&txn)
+        .await?;
+
+    if updated.rows_affected == 0 {
+        return Err(AppError::NotFound("Member"));
+    }
+
+    ensure_has_owner(&txn, user.household_id).await?;
+    txn.commit().await?;
+
+    Ok(Redirect::to("/household").into_response())
+}
+
+/// The removed user gets a fresh household of their own.
+#[utoipa::path(
+    post,
+    path = "/household/members/{user_id}/remove",
+    tag = "household",
+    params(("user_id" = i32, Path)),
+    responses(
+        (status = 303, description = "Member removed"),
+        (status = 409, description = "The household would be left without an owner"),
+        (status = 403, description = "Only owners can remove members"),
+        (status = 404, description = "Not a member of the household"),
+    )
+)]
+pub async fn remove_member(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((member_id,)): Path<(i32,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Owner)?;
+
+    let txn = db.begin().await?;
+
+    let removed = HouseholdMembers::delete_many()
+        .filter(household_members::Column::HouseholdId.eq(user.household_id))
+        .filter(household_members::Column::UserId.eq(member_id))
+        .exec(&txn)
+        .await?;
+
+    if removed.rows_affected == 0 {
+        return Err(AppError::NotFound("Member"));
+    }
+
+    ensure_has_owner(&txn, user.household_id).await?;
+
+    let member = users::Entity::find_by_id(member_id)
+        .one(&txn)
+        .await?
+        .ok_or(anyhow!("User not found"))?;
+    create_for(&txn, &member).await?;
+
+    txn.commit().await?;
+
+    Ok(Redirect::to("/household").into_response())
+}
+
+/// Households must always keep at least one owner to manage them.
+async fn ensure_has_owner<C: ConnectionTrait>(db: &C, household_id: i32) -> error::Result<()> {
+    let owners = HouseholdMembers::find()
+        .filter(household_members::Column::HouseholdId.eq(household_id))
+        .filter(household_members::Column::Role.eq(HouseholdRole::Owner))
+        .count(db)
+        .await?;
+
+    if owners == 0 {
+        return Err(AppError::Conflict(anyhow!(
+            "A household must keep at least one owner"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Move everything household `from` has into household `to`, so deleting it doesn't cascade to
+/// its library. Collections named like one `to` already has are numbered, e.g. "Dinner 2".
+async fn move_household<C: ConnectionTrait>(db: &C, from: i32, to: i32) -> error::Result<()> {
+    recipes::Entity::update_many()
+        .col_expr(recipes::Column::HouseholdId, Expr::value(to))
+        .filter(recipes::Column::HouseholdId.eq(from))
+        .exec(db)
+        .await?;
+
+    let mut names: HashSet<String> = Collections::find()
+        .select_only()
+        .column(collections::Column::Name)
+        .filter(collections::Column::HouseholdId.eq(to))
+        .into_tuple()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+
+    let moving = Collections::find()
+        .filter(collections::Column::HouseholdId.eq(from))
+        .all(db)
+        .await?;
+
+    for collection in moving {
+        let mut name = collection.name.clone();
+        for n in 2.. {
+            if names.insert(name.clone()) {
+                break;
+            }
+            name = format!("{} {n}", collection.name);
+        }
+
+        collections::ActiveModel {
+            id: Set(collection.id),
+            household_id: Set(to),
+            name: Set(name),
+            ..Default::default()
+        }
+        .update(db)
+        .await?;
+    }
+
+    ImportBatches::update_many()
+        .col_expr(import_batches::Column::HouseholdId, Expr::value(to))
+        .filter(import_batches::Column::HouseholdId.eq(from))
+        .exec(db)
+        .await?;
+
+    // A video both households failed on keeps the failure `to` already has
+    LlmExtractionFailures::delete_many()
+        .filter(llm_extraction_failures::Column::HouseholdId.eq(from))
+        .filter(
+            llm_extraction_failures::Column::InstagramVideoId.in_subquery(
+                SeaQuery::select()
+                    .column(llm_extraction_failures::Column::InstagramVideoId)
+                    .from(llm_extraction_failures::Entity)
+                    .and_where(llm_extraction_failures::Column::HouseholdId.eq(to))
+                    .to_owned(),
+            ),
+        )
+        .exec(db)
+        .await?;
+
+    LlmExtractionFailures::update_many()
+        .col_expr(
+            llm_extraction_failures::Column::HouseholdId,
+            Expr::value(to),
+        )
+        .filter(llm_extraction_failures::Column::HouseholdId.eq(from))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn find_invitation(
+    db: &DatabaseConnection,
+    token: &str,
+) -> error::Result<Option<(household_invitations::Model, households::Model)>> {
+    let invitation = HouseholdInvitations::find_by_id(auth::hash_token(token))
+        .filter(household_invitations::Column::AcceptedAt.is_null())
+        .filter(household_invitations::Column::ExpiresAt.gt(chrono::Utc::now()))
+        .find_also_related(Households)
+        .one(db)
+        .await?;
+
+    Ok(invitation.and_then(|(invitation, household)| Some((invitation, household?))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/invitations/{token}",
+    tag = "household",
+    params(("token" = String, Path)),
+    responses(
+        (status = 200, content_type = "text/html"),
+        (status = 404, description = "Unknown, used or expired invitation"),
+    )
+)]
+pub async fn show_invitation(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((token,)): Path<(String,)>,
+) -> error::Result<Response> {
+    let Some((invitation, household)) = find_invitation(&db, &token).await? else {
+        return Err(AppError::NotFound("Invitation"));
+    };
+
+    Ok(RenderHtml(
+        "households/invitation.html",
+        template_engine,
+        json!({
+            "token": token,
+            "household": household,
+            "role": invitation.role,
+            "expires_at": invitation.expires_at,
+            "already_member": household.id == user.household_id,
+        }),
+    )
+    .into_response())
+}
+
+/// Moves the user into the inviting household. If that leaves their old household empty its
+/// recipes come with them.
+#[utoipa::path(
+    post,
+    path = "/invitations/{token}",
+    tag = "household",
+    params(("token" = String, Path)),
+    responses(
+        (status = 303, description = "Joined the household"),
+        (status = 404, description = "Unknown, used or expired invitation"),
+    )
+)]
+pub async fn accept_invitation(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((token,)): Path<(String,)>,
+) -> error::Result<Response> {
+    let Some((invitation, household)) = find_invitation(&db, &token).await? else {
+        return Err(AppError::NotFound("Invitation"));
+    };
+
+    if household.id == user.household_id {
+        return Ok(Redirect::to("/household").into_response());
+    }
+
+    let txn = db.begin().await?;
+
+    // Claim the invitation first, so of two people racing to use it only one gets in
+    let now = chrono::Utc::now().fixed_offset();
+    let claimed = HouseholdInvitations::update_many()
+        .col_expr(
+            household_invitations::Column::AcceptedBy,
+            Expr::value(user.id),
+        )
+        .col_expr(household_invitations::Column::AcceptedAt, Expr::value(now))
+        .filter(household_invitations::Column::TokenHash.eq(invitation.token_hash))
+        .filter(household_invitations::Column::AcceptedAt.is_null())
+        .filter(household_invitations::Column::ExpiresAt.gt(now))
+        .exec(&txn)
+        .await?;
+
+    if claimed.rows_affected == 0 {
+        return Err(AppError::NotFound("Invitation"));
+    }
+
+    HouseholdMembers::delete_many()
+        .filter(household_members::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await?;
+
+    household_members::ActiveModel {
+        household_id: Set(household.id),
+        user_id: Set(user.id),
+        role: Set(invitation.role),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+    }
+    .insert(&txn)
+    .await?;
+
+    let remaining = HouseholdMembers::find()
+        .filter(household_members::Column::HouseholdId.eq(user.household_id))
+        .count(&txn)
+        .await?;
+
+    if remaining == 0 {
+        move_household(&txn, user.household_id, household.id).await?;
+
+        Households::delete_by_id(user.household_id)
+            .exec(&txn)
+            .await?;
+    } else {
+        // Someone else still needs to be able to manage the household we're leaving
+        ensure_has_owner(&txn, user.household_id).await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(Redirect::to("/recipes").into_response())
+}
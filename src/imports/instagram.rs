@@ -0,0 +1,258 @@
+//! Reading the saved posts out of Instagram's "Download your information" export, either the zip
+//! as downloaded or the folder it extracts to. Only the JSON flavour of the export is supported.
+//!
+//! `saved_posts.json` lists everything saved, `saved_collections.json` lists each collection
+//! as a header entry followed by the posts in it.
+
+use super::ImportLink;
+use crate::share;
+use anyhow::{bail, Context};
+use serde_json::Value;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+const SAVED_POSTS: &str = "saved_posts.json";
+const SAVED_COLLECTIONS: &str = "saved_collections.json";
+
+/// Largest saved posts file read out of a zip. Even tens of thousands of saves are a few MB, this
+/// stops a small zip from decompressing into something that fills the memory.
+const MAX_SAVED_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Links from whichever of the two saved files `value` is, `None` if it's neither.
+pub fn links_from_json(value: &Value) -> Option<Vec<ImportLink>> {
+    if let Some(media) = value.get("saved_saved_media").and_then(Value::as_array) {
+        return Some(saved_posts(media));
+    }
+
+    if let Some(entries) = value
+        .get("saved_saved_collections")
+        .and_then(Value::as_array)
+    {
+        return Some(saved_collections(entries));
+    }
+
+    None
+}
+
+fn href(entry: &Value) -> Option<String> {
+    entry
+        .get("string_map_data")?
+        .as_object()?
+        .values()
+        .find_map(|field| field.get("href")?.as_str())
+        .and_then(share::normalise_url)
+}
+
+fn saved_posts(media: &[Value]) -> Vec<ImportLink> {
+    media
+        .iter()
+        .filter_map(href)
+        .map(|url| ImportLink {
+            url,
+            collections: vec![],
+        })
+        .collect()
+}
+
+fn saved_collections(entries: &[Value]) -> Vec<ImportLink> {
+    let mut links = vec![];
+    let mut collection = None;
+
+    for entry in entries {
+        if entry.get("title").and_then(Value::as_str) == Some("Collection") {
+            collection = entry
+                .pointer("/string_map_data/Name/value")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            continue;
+        }
+
+        if let (Some(url), Some(collection)) = (href(entry), &collection) {
+            links.push(ImportLink {
+                url,
+                collections: vec![collection.clone()],
+            });
+        }
+    }
+
+    links
+}
+
+fn parse_file(name: &str, contents: &str) -> anyhow::Result<Vec<ImportLink>> {
+    let value: Value = serde_json::from_str(contents).with_context(|| format!("Reading {name}"))?;
+    Ok(links_from_json(&value).unwrap_or_default())
+}
+
+/// Saved posts from an export zip.
+pub fn read_zip<R: Read + Seek>(reader: R) -> anyhow::Result<Vec<ImportLink>> {
+    let mut archive = zip::ZipArchive::new(reader).context("Not a zip file")?;
+    let mut links = vec![];
+    let mut found = false;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        let Some(name) = file
+            .enclosed_name()
+            .and_then(|path| path.file_name()?.to_str().map(str::to_string))
+        else {
+            continue;
+        };
+
+        if name == SAVED_POSTS || name == SAVED_COLLECTIONS {
+            // The size in the header is only what the zip claims, so the read is capped as well
+            if file.size() > MAX_SAVED_FILE_BYTES {
+                bail!("{name} is too large");
+            }
+
+            let mut contents = String::new();
+            file.by_ref()
+                .take(MAX_SAVED_FILE_BYTES + 1)
+                .read_to_string(&mut contents)?;
+
+            if contents.len() as u64 > MAX_SAVED_FILE_BYTES {
+                bail!("{name} is too large");
+            }
+
+            links.extend(parse_file(&name, &contents)?);
+            found = true;
+        }
+    }
+
+    if !found {
+        bail!("No {SAVED_POSTS} in the archive, was it exported as JSON?");
+    }
+
+    Ok(links)
+}
+
+/// Saved posts from an export zip or the folder it was extracted to.
+pub fn read_archive(path: &Path) -> anyhow::Result<Vec<ImportLink>> {
+    if !path.is_dir() {
+        let file = std::fs::File::open(path).with_context(|| format!("Opening {path:?}"))?;
+        return read_zip(std::io::BufReader::new(file));
+    }
+
+    let mut links = vec![];
+    let mut found = false;
+    let mut dirs = vec![path.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+
+            if name == SAVED_POSTS || name == SAVED_COLLECTIONS {
+                links.extend(parse_file(name, &std::fs::read_to_string(&path)?)?);
+                found = true;
+            }
+        }
+    }
+
+    if !found {
+        bail!("No {SAVED_POSTS} under {path:?}, was it exported as JSON?");
+    }
+
+    Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn saved(href: &str) -> Value {
+        json!({
+            "title": "someone",
+            "string_map_data": {
+                "Saved on": { "href": href, "timestamp": 1714000000 }
+            }
+        })
+    }
+
+    fn collection(name: &str) -> Value {
+        json!({
+            "title": "Collection",
+            "string_map_data": {
+                "Name": { "value": name },
+                "Creation Time": { "timestamp": 1714000000 }
+            }
+        })
+    }
+
+    #[test]
+    fn reads_saved_posts() {
+        let value = json!({
+            "saved_saved_media": [
+                saved("https://www.instagram.com/reel/AAA/?igsh=x"),
+                { "title": "no link", "string_map_data": {} },
+                saved("https://www.instagram.com/p/BBB/"),
+            ]
+        });
+
+        assert_eq!(
+            links_from_json(&value).unwrap(),
+            [
+                ImportLink {
+                    url: "https://www.instagram.com/reel/AAA/".to_string(),
+                    collections: vec![],
+                },
+                ImportLink {
+                    url: "https://www.instagram.com/p/BBB/".to_string(),
+                    collections: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_each_collection_header_and_its_posts() {
+        let value = json!({
+            "saved_saved_collections": [
+                saved("https://www.instagram.com/reel/ORPHAN/"),
+                collection("Dinner"),
+                saved("https://www.instagram.com/reel/AAA/"),
+                saved("https://www.instagram.com/reel/BBB/"),
+                collection("Baking"),
+                saved("https://www.instagram.com/reel/AAA/"),
+            ]
+        });
+
+        let links: Vec<_> = links_from_json(&value)
+            .unwrap()
+            .into_iter()
+            .map(|link| (link.url, link.collections))
+            .collect();
+
+        assert_eq!(
+            links,
+            [
+                (
+                    "https://www.instagram.com/reel/AAA/".to_string(),
+                    vec!["Dinner".to_string()]
+                ),
+                (
+                    "https://www.instagram.com/reel/BBB/".to_string(),
+                    vec!["Dinner".to_string()]
+                ),
+                (
+                    "https://www.instagram.com/reel/AAA/".to_string(),
+                    vec!["Baking".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_json_is_not_an_export() {
+        assert_eq!(links_from_json(&json!({ "saved": [] })), None);
+    }
+}
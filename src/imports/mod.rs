@@ -0,0 +1,730 @@
+//! Bulk import of reel links, pasted or uploaded as text, CSV or Instagram's "saved posts" JSON
+//! export. Downloads are spread out over time so Instagram doesn't rate limit us, and each batch
+//! gets a page tracking how far along its links are.
+
+use crate::auth::CurrentUser;
+use crate::cli::ImportInstagramArgs;
+use crate::entities::prelude::{ImportBatches, ImportItems};
+use crate::entities::sea_orm_active_enums::{HouseholdRole, ImportItemStatus};
+use crate::entities::{import_batches, import_item_collections, import_items};
+use crate::error::{self, AppError};
+use crate::jobs::fetch_post::FetchJob;
+use crate::jobs::{self, FETCH_REEL_TASK_TYPE};
+use crate::{collections, negotiate, share};
+use anyhow::anyhow;
+use axum::extract::{Multipart, Path};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use fang::AsyncRunnable;
+use minijinja_autoreload::AutoReloader;
+use sea_orm::prelude::Uuid;
+use sea_orm::sea_query::Expr;
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction,
+    EntityTrait, FromQueryResult, QueryFilter, QueryOrder, QuerySelect, Set, Statement,
+    TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+pub mod instagram;
+
+pub(crate) const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Uploads can be whole Instagram data exports, which are well past axum's default body limit.
+pub const MAX_UPLOAD_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportSettings {
+    /// Gap between the downloads queued by an import.
+    pub interval: Duration,
+}
+
+/// A link to import, and the Instagram collections it was saved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportLink {
+    pub url: String,
+    pub collections: Vec<String>,
+}
+
+/// Every link in an upload. Instagram's saved posts files keep their collections, other JSON is
+/// walked value by value so links inside escaped strings are found too, anything else (plain
+/// text, CSV) is searched as is.
+fn parse_links(text: &str) -> Vec<ImportLink> {
+    fn walk(value: &Value, urls: &mut Vec<String>) {
+        match value {
+            Value::String(s) => urls.extend(share::extract_urls(s)),
+            Value::Array(values) => values.iter().for_each(|v| walk(v, urls)),
+            Value::Object(map) => map.values().for_each(|v| walk(v, urls)),
+            _ => {}
+        }
+    }
+
+    let mut urls = vec![];
+
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) => {
+            if let Some(links) = instagram::links_from_json(&value) {
+                return links;
+            }
+
+            walk(&value, &mut urls)
+        }
+        Err(_) => urls.extend(share::extract_urls(text)),
+    }
+
+    urls.into_iter()
+        .map(|url| ImportLink {
+            url,
+            collections: vec![],
+        })
+        .collect()
+}
+
+/// Merge links to the same URL, keeping the first's position and every collection.
+fn merge_links(links: Vec<ImportLink>) -> Vec<ImportLink> {
+    let mut merged: Vec<ImportLink> = vec![];
+    let mut positions = HashMap::new();
+
+    for link in links {
+        match positions.get(&link.url) {
+            Some(&i) => {
+                let existing: &mut ImportLink = &mut merged[i];
+
+                for collection in link.collections {
+                    if !existing.collections.contains(&collection) {
+                        existing.collections.push(collection);
+                    }
+                }
+            }
+
+            None => {
+                positions.insert(link.url.clone(), merged.len());
+                merged.push(link);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Reels the household already has a recipe for, that an earlier import downloaded and whose
+/// recipe may still be on its way, or that are waiting to download however they were queued.
+async fn known_reel_ids<C: ConnectionTrait>(
+    db: &C,
+    household_id: i32,
+) -> error::Result<HashSet<String>> {
+    #[derive(FromQueryResult)]
+    struct Known {
+        reel_id: String,
+    }
+
+    let known = Known::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        select iv.instagram_id as reel_id
+from recipes r
+         join instagram_video iv on iv.id = r.instagram_video_id
+where r.household_id = $1
+union
+select i.reel_id
+from import_items i
+         join import_batches b on b.id = i.batch_id
+         join instagram_video iv on iv.instagram_id = i.reel_id
+where b.household_id = $1
+union
+select coalesce(t.metadata ->> 'reel_id', t.metadata ->> 'post_id')
+from fang_tasks t
+where t.task_type = $2
+  and t.state in ('new', 'in_progress', 'retried')
+  and (t.metadata ->> 'household_id')::integer = $1
+  and coalesce(t.metadata ->> 'reel_id', t.metadata ->> 'post_id') is not null;
+        "#,
+        vec![household_id.into(), FETCH_REEL_TASK_TYPE.into()],
+    ))
+    .all(db)
+    .await?;
+
+    Ok(known.into_iter().map(|k| k.reel_id).collect())
+}
+
+/// When the next import download can run, after any already waiting in the queue.
+async fn next_slot(
+    txn: &DatabaseTransaction,
+    interval: Duration,
+) -> error::Result<chrono::DateTime<chrono::Utc>> {
+    #[derive(FromQueryResult)]
+    struct Latest {
+        scheduled_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    let latest = Latest::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        "select max(scheduled_at) as scheduled_at from fang_tasks where task_type = $1 and state in ('new', 'retried');",
+        vec![FETCH_REEL_TASK_TYPE.into()],
+    ))
+    .one(txn)
+    .await?
+    .and_then(|l| l.scheduled_at);
+
+    let now = chrono::Utc::now();
+
+    Ok(match latest {
+        Some(latest) if latest > now => latest + interval,
+        _ => now,
+    })
+}
+
+/// Queue `job` to run no earlier than `at`, unless the same job is already waiting. fang's own
+/// `schedule_task` only takes cron-style schedules, so this writes the row the same way its
+/// `insert_task` would, uniqueness hash included, inside our transaction.
+async fn schedule_fetch(
+    txn: &DatabaseTransaction,
+    job: &dyn AsyncRunnable,
+    at: chrono::DateTime<chrono::Utc>,
+) -> error::Result<Uuid> {
+    #[derive(FromQueryResult)]
+    struct Inserted {
+        id: Uuid,
+    }
+
+    if let Some(id) = jobs::find_queued(txn, job).await? {
+        return Ok(id);
+    }
+
+    let metadata = serde_json::to_value(job).map_err(anyhow::Error::from)?;
+    let uniq_hash = hex::encode(Sha256::digest(metadata.to_string().as_bytes()));
+
+    let inserted = Inserted::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"insert into fang_tasks (metadata, task_type, uniq_hash, scheduled_at) values ($1, $2, $3, $4) returning id;"#,
+        vec![
+            metadata.into(),
+            job.task_type().into(),
+            uniq_hash.into(),
+            at.into(),
+        ],
+    ))
+    .one(txn)
+    .await?
+    .ok_or(anyhow!("Task insert returned nothing"))?;
+
+    Ok(inserted.id)
+}
+
+/// Record every link in the batch and queue downloads for the new ones, `interval` apart.
+/// Recipes already in the library are added to the links' collections straight away, the rest
+/// once they have been extracted.
+pub async fn create_batch(
+    db: &DatabaseConnection,
+    user: &CurrentUser,
+    source: String,
+    links: Vec<ImportLink>,
+    interval: Duration,
+) -> error::Result<import_batches::Model> {
+    let txn = db.begin().await?;
+
+    let batch = import_batches::ActiveModel {
+        household_id: Set(user.household_id),
+        created_by: Set(user.id),
+        source: Set(source),
+        created_at: Set(chrono::Utc::now().fixed_offset()),
+
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    let mut known = known_reel_ids(&txn, user.household_id).await?;
+    let mut slot = next_slot(&txn, interval).await?;
+
+    for ImportLink { url, collections } in merge_links(links) {
+        let mut item = import_items::ActiveModel {
+            batch_id: Set(batch.id),
+            url: Set(url.clone()),
+
+            ..Default::default()
+        };
+
+        match FetchJob::new(url, true, user.id, user.household_id) {
+            Err(_) => item.status = Set(ImportItemStatus::Invalid),
+
+            // The same reel is often saved under several URLs, so dedupe on its id too
+            Ok(job) if !known.insert(job.instagram_id().to_string()) => {
+                item.reel_id = Set(Some(job.instagram_id().to_string()));
+                item.status = Set(ImportItemStatus::Duplicate);
+            }
+
+            Ok(job) => {
+                let task_id = schedule_fetch(&txn, job.job(), slot).await?;
+
+                item.reel_id = Set(Some(job.instagram_id().to_string()));
+                item.status = Set(ImportItemStatus::Queued);
+                item.task_id = Set(Some(task_id));
+                item.scheduled_at = Set(Some(slot.fixed_offset()));
+
+                slot += interval;
+            }
+        }
+
+        let item = item.insert(&txn).await?;
+
+        for name in collections {
+            let collection = collections::find_or_create(&txn, user.household_id, &name).await?;
+
+            import_item_collections::ActiveModel {
+                import_item_id: Set(item.id),
+                collection_id: Set(collection.id),
+            }
+            .insert(&txn)
+            .await?;
+        }
+    }
+
+    collections::add_imported_recipes(&txn, user.household_id, None).await?;
+
+    txn.commit().await?;
+
+    Ok(batch)
+}
+
+/// Where a link in an import has got to.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemProgress {
+    /// Not an Instagram reel or post link.
+    Invalid,
+    /// Already in the library, or earlier in the same import.
+    Duplicate,
+    /// Waiting for its turn to download.
+    Scheduled,
+    Fetching,
+    /// Failed at least once, will be tried again.
+    Retrying,
+    /// Downloaded, its recipe appears once the LLM has been through it.
+    Done,
+    Failed,
+}
+
+#[derive(FromQueryResult)]
+struct ItemRow {
+    id: i32,
+    url: String,
+    reel_id: Option<String>,
+    status: ImportItemStatus,
+    scheduled_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    task_state: Option<String>,
+    error_message: Option<String>,
+    video_id: Option<i32>,
+    recipe_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ImportItem {
+    id: i32,
+    url: String,
+    reel_id: Option<String>,
+    progress: ItemProgress,
+    #[schema(value_type = Option<String>, format = DateTime)]
+    scheduled_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    error_message: Option<String>,
+    video_id: Option<i32>,
+    recipe_id: Option<i32>,
+}
+
+impl From<ItemRow> for ImportItem {
+    fn from(row: ItemRow) -> Self {
+        let progress = match (row.status, row.task_state.as_deref()) {
+            (ImportItemStatus::Invalid, _) => ItemProgress::Invalid,
+            (ImportItemStatus::Duplicate, _) => ItemProgress::Duplicate,
+            (ImportItemStatus::Queued, Some("new")) => ItemProgress::Scheduled,
+            (ImportItemStatus::Queued, Some("in_progress")) => ItemProgress::Fetching,
+            (ImportItemStatus::Queued, Some("retried")) => ItemProgress::Retrying,
+            (ImportItemStatus::Queued, Some("failed")) => ItemProgress::Failed,
+
+            // The queue drops tasks once they succeed, and dropped failed ones too before it kept
+            // them, so whether the video made it is the only record of how it went
+            (ImportItemStatus::Queued, _) if row.video_id.is_some() => ItemProgress::Done,
+            (ImportItemStatus::Queued, _) => ItemProgress::Failed,
+        };
+
+        ImportItem {
+            id: row.id,
+            url: row.url,
+            reel_id: row.reel_id,
+            progress,
+            scheduled_at: row.scheduled_at,
+            error_message: row.error_message,
+            video_id: row.video_id,
+            recipe_id: row.recipe_id,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ImportReport {
+    id: i32,
+    /// "pasted", or the name of the uploaded file.
+    source: String,
+    #[schema(value_type = String, format = DateTime)]
+    created_at: chrono::DateTime<chrono::FixedOffset>,
+    /// No link is still waiting to download.
+    finished: bool,
+    items: Vec<ImportItem>,
+}
+
+async fn load_report(
+    db: &DatabaseConnection,
+    user: &CurrentUser,
+    id: i32,
+) -> error::Result<ImportReport> {
+    let batch = ImportBatches::find_by_id(id)
+        .filter(import_batches::Column::HouseholdId.eq(user.household_id))
+        .one(db)
+        .await?
+        .ok_or(AppError::NotFound("Import"))?;
+
+    let items: Vec<ImportItem> = ItemRow::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        select i.id,
+       i.url,
+       i.reel_id,
+       i.status::text as status,
+       i.scheduled_at,
+       t.state::text as task_state,
+       t.error_message,
+       iv.id         as video_id,
+       r.id          as recipe_id
+from import_items i
+         left join fang_tasks t on t.id = i.task_id
+         left join lateral (select id
+                            from instagram_video
+                            where instagram_id = i.reel_id
+                            order by id
+                            limit 1) iv on true
+         left join lateral (select id
+                            from recipes
+                            where instagram_video_id = iv.id
+                              and household_id = $2
+                            order by id
+                            limit 1) r on true
+where i.batch_id = $1
+order by i.id;
+        "#,
+        vec![batch.id.into(), user.household_id.into()],
+    ))
+    .all(db)
+    .await?
+    .into_iter()
+    .map(ImportItem::from)
+    .collect();
+
+    let finished = !items.iter().any(|item| {
+        matches!(
+            item.progress,
+            ItemProgress::Scheduled | ItemProgress::Fetching | ItemProgress::Retrying
+        )
+    });
+
+    Ok(ImportReport {
+        id: batch.id,
+        source: batch.source,
+        created_at: batch.created_at,
+        finished,
+        items,
+    })
+}
+
+#[derive(Serialize, FromQueryResult)]
+struct BatchSummary {
+    id: i32,
+    source: String,
+    created_at: chrono::DateTime<chrono::FixedOffset>,
+    items: i64,
+    queued: i64,
+}
+
+/// The household's imports, most recent first, and the form to start another.
+#[utoipa::path(
+    get,
+    path = "/imports",
+    tag = "recipes",
+    responses((status = 200, content_type = "text/html"))
+)]
+pub async fn imports_index(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let batches = ImportBatches::find()
+        .select_only()
+        .column(import_batches::Column::Id)
+        .column(import_batches::Column::Source)
+        .column(import_batches::Column::CreatedAt)
+        .column_as(import_items::Column::Id.count(), "items")
+        .column_as(
+            Expr::cust("count(*) filter (where import_items.status = 'queued')"),
+            "queued",
+        )
+        .left_join(ImportItems)
+        .filter(import_batches::Column::HouseholdId.eq(user.household_id))
+        .group_by(import_batches::Column::Id)
+        .order_by_desc(import_batches::Column::CreatedAt)
+        .into_model::<BatchSummary>()
+        .all(&db)
+        .await?;
+
+    Ok(RenderHtml(
+        "imports/index.html",
+        template_engine,
+        json!({
+            "batches": batches,
+            "can_edit": user.require(HouseholdRole::Editor).is_ok(),
+        }),
+    ))
+}
+
+/// The fields of the import form, both optional but at least one must contain a link.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct ImportForm {
+    /// Links pasted one per line, or in any other text.
+    urls: Option<String>,
+    /// A text or CSV file, or Instagram's data export as a zip or just its `saved_posts.json` or
+    /// `saved_collections.json`.
+    #[schema(value_type = Option<String>, format = Binary)]
+    file: Option<Vec<u8>>,
+}
+
+/// Start an import, queueing downloads for every new reel linked from the pasted text or upload.
+#[utoipa::path(
+    post,
+    path = "/imports",
+    tag = "recipes",
+    request_body(content_type = "multipart/form-data", content = inline(ImportForm)),
+    responses(
+        (status = 303, description = "Import started, redirects to its progress page"),
+        (status = 400, description = "No links in what was submitted"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
+pub async fn create_import(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(settings): Extension<ImportSettings>,
+    mut multipart: Multipart,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Editor)?;
+
+    let mut source = None;
+    let mut links = vec![];
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(e.into()))?
+    {
+        let file_name = field
+            .file_name()
+            .filter(|n| !n.is_empty())
+            .map(str::to_string);
+
+        match field.name() {
+            Some("urls") => {}
+            Some("file") if file_name.is_some() => source = file_name,
+            _ => continue,
+        }
+
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| AppError::BadRequest(e.into()))?;
+
+        if bytes.starts_with(ZIP_MAGIC) {
+            links.extend(instagram::read_zip(Cursor::new(bytes)).map_err(AppError::BadRequest)?);
+        } else {
+            links.extend(parse_links(&String::from_utf8_lossy(&bytes)));
+        }
+    }
+
+    if links.is_empty() {
+        return Err(AppError::BadRequest(anyhow!(
+            "No links found in what was submitted"
+        )));
+    }
+
+    let source = source.unwrap_or_else(|| "pasted".to_string());
+    tracing::info!("Importing {} links from {source}", links.len());
+
+    let batch = create_batch(&db, &user, source, links, settings.interval).await?;
+
+    Ok(Redirect::to(&format!("/imports/{}", batch.id)))
+}
+
+/// Progress of an import, link by link.
+#[utoipa::path(
+    get,
+    path = "/imports/{id}",
+    tag = "recipes",
+    params(("id" = i32, Path, description = "Import id")),
+    responses(
+        (status = 200, content(
+            (ImportReport = "application/json"),
+            ("text/html"),
+        )),
+        (status = 404, description = "No such import in the household"),
+    )
+)]
+pub async fn show_import(
+    user: CurrentUser,
+    header_map: HeaderMap,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Path((id,)): Path<(i32,)>,
+) -> error::Result<Response> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let report = load_report(&db, &user, id).await?;
+
+    if negotiate::wants_json(&header_map) {
+        Ok(Json(report).into_response())
+    } else {
+        Ok(RenderHtml("imports/show.html", template_engine, json!(report)).into_response())
+    }
+}
+
+/// The user called `username` as the CLI subcommands act on their behalf.
+pub async fn find_user(db: &DatabaseConnection, username: &str) -> anyhow::Result<CurrentUser> {
+    CurrentUser::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        select u.id, u.username, m.household_id, m.role::text as role
+from users u
+         join household_members m on m.user_id = u.id
+where u.username = $1;
+        "#,
+        vec![username.into()],
+    ))
+    .one(db)
+    .await?
+    .ok_or_else(|| anyhow!("No user called {username}"))
+}
+
+/// Backs the `import-instagram` subcommand, importing an export too big to upload comfortably.
+pub async fn import_instagram(
+    db: &DatabaseConnection,
+    args: &ImportInstagramArgs,
+) -> anyhow::Result<()> {
+    let user = find_user(db, &args.username).await?;
+
+    user.require(HouseholdRole::Editor)
+        .map_err(|_| anyhow!("{} can't add recipes to their household", user.username))?;
+
+    let links = instagram::read_archive(&args.path)?;
+
+    let source = args.path.file_name().map_or_else(
+        || "instagram".to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    );
+
+    let batch = create_batch(
+        db,
+        &user,
+        source,
+        links,
+        Duration::from_secs(args.import_interval),
+    )
+    .await?;
+
+    tracing::info!(
+        "Created import {}, follow it at /imports/{}",
+        batch.id,
+        batch.id
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, collections: &[&str]) -> ImportLink {
+        ImportLink {
+            url: url.to_string(),
+            collections: collections.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_plain_text_and_csv() {
+        let text = "url,note\nhttps://instagram.com/reels/AAA/?igsh=x,pasta\n\
+                    https://www.instagram.com/p/BBB/,\"soup, later\"";
+
+        assert_eq!(
+            parse_links(text),
+            [
+                link("https://www.instagram.com/reel/AAA/", &[]),
+                link("https://www.instagram.com/p/BBB/", &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn walks_arbitrary_json() {
+        let text = r#"{"saved": [{"link": "https://www.instagram.com/reel/AAA/"}], "notes": "see https://www.instagram.com/p/BBB/."}"#;
+
+        let mut urls: Vec<_> = parse_links(text).into_iter().map(|l| l.url).collect();
+        urls.sort();
+        assert_eq!(
+            urls,
+            [
+                "https://www.instagram.com/p/BBB/",
+                "https://www.instagram.com/reel/AAA/",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_instagram_collections() {
+        let text = r#"{"saved_saved_collections": [
+            {"title": "Collection", "string_map_data": {"Name": {"value": "Dinner"}}},
+            {"string_map_data": {"Name": {"href": "https://www.instagram.com/reel/AAA/"}}}
+        ]}"#;
+
+        assert_eq!(
+            parse_links(text),
+            [link("https://www.instagram.com/reel/AAA/", &["Dinner"])]
+        );
+    }
+
+    #[test]
+    fn merges_duplicate_links() {
+        let links = vec![
+            link("https://www.instagram.com/reel/AAA/", &[]),
+            link("https://www.instagram.com/reel/BBB/", &["Dinner"]),
+            link("https://www.instagram.com/reel/AAA/", &["Dinner"]),
+            link("https://www.instagram.com/reel/AAA/", &["Baking", "Dinner"]),
+        ];
+
+        assert_eq!(
+            merge_links(links),
+            [
+                link("https://www.instagram.com/reel/AAA/", &["Dinner", "Baking"]),
+                link("https://www.instagram.com/reel/BBB/", &["Dinner"]),
+            ]
+        );
+    }
+}
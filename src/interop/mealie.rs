@@ -0,0 +1,255 @@
+//! Mealie's recipe JSON, either a single recipe or array of them as the API returns, or Mealie's
+//! export zip with a `recipes/<slug>/<slug>.json` for each recipe and its photo in
+//! `recipes/<slug>/images/original.<ext>`.
+
+use super::{lines, non_empty, parse_minutes, read_entry, FileNames, Image, PortableRecipe};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{Cursor, Read, Seek, Write};
+use zip::write::FileOptions;
+
+/// Mealie keeps ingredients as plain text or, with ingredient parsing on, split into parts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MealieIngredient {
+    Text(String),
+    Parsed {
+        #[serde(default)]
+        display: Option<String>,
+        #[serde(default, rename = "originalText")]
+        original_text: Option<String>,
+        #[serde(default)]
+        note: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MealieInstruction {
+    Text(String),
+    Step { text: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Note {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct MealieRecipe {
+    name: Option<String>,
+    slug: Option<String>,
+    recipe_ingredient: Vec<MealieIngredient>,
+    recipe_instructions: Vec<MealieInstruction>,
+    notes: Vec<Note>,
+    /// A string, or a number from older versions.
+    recipe_yield: Option<Value>,
+    prep_time: Option<String>,
+    perform_time: Option<String>,
+    cook_time: Option<String>,
+    #[serde(rename = "orgURL")]
+    org_url: Option<String>,
+    tags: Vec<Named>,
+    recipe_category: Vec<Named>,
+}
+
+impl MealieIngredient {
+    fn into_text(self) -> Option<String> {
+        match self {
+            MealieIngredient::Text(text) => non_empty(Some(text)),
+            MealieIngredient::Parsed {
+                display,
+                original_text,
+                note,
+            } => non_empty(display)
+                .or(non_empty(original_text))
+                .or(non_empty(note)),
+        }
+    }
+}
+
+impl MealieRecipe {
+    fn into_portable(self, image: Option<Image>) -> PortableRecipe {
+        let notes = self
+            .notes
+            .into_iter()
+            .filter_map(|note| {
+                let text = non_empty(note.text)?;
+                Some(match non_empty(note.title) {
+                    Some(title) => format!("{title}: {text}"),
+                    None => text,
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let servings = match self.recipe_yield {
+            Some(Value::String(text)) => non_empty(Some(text)),
+            Some(Value::Number(number)) => Some(number.to_string()),
+            _ => None,
+        };
+
+        // Collections cover both, adding a recipe to one twice is harmless
+        let tags = self
+            .recipe_category
+            .into_iter()
+            .chain(self.tags)
+            .map(|named| named.name)
+            .collect();
+
+        PortableRecipe {
+            title: non_empty(self.name)
+                .or(non_empty(self.slug))
+                .unwrap_or_else(|| "Untitled recipe".to_string()),
+            ingredients: self
+                .recipe_ingredient
+                .into_iter()
+                .filter_map(MealieIngredient::into_text)
+                .collect(),
+            instructions: self
+                .recipe_instructions
+                .into_iter()
+                .flat_map(|instruction| match instruction {
+                    MealieInstruction::Text(text) | MealieInstruction::Step { text } => {
+                        lines(&text)
+                    }
+                })
+                .collect(),
+            notes: non_empty(Some(notes)),
+            servings,
+            prep_minutes: self.prep_time.as_deref().and_then(parse_minutes),
+            cook_minutes: self
+                .perform_time
+                .or(self.cook_time)
+                .as_deref()
+                .and_then(parse_minutes),
+            source_url: non_empty(self.org_url),
+            tags,
+            image,
+        }
+    }
+}
+
+/// A recipe or array of recipes.
+pub(super) fn from_json(value: Value) -> anyhow::Result<Vec<PortableRecipe>> {
+    let recipes: Vec<MealieRecipe> = match value {
+        Value::Array(_) => serde_json::from_value(value)?,
+        Value::Object(_) => vec![serde_json::from_value(value)?],
+        _ => bail!("Not a Mealie recipe"),
+    };
+
+    Ok(recipes
+        .into_iter()
+        .map(|recipe| recipe.into_portable(None))
+        .collect())
+}
+
+pub(super) fn read_archive<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> anyhow::Result<Vec<PortableRecipe>> {
+    let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    let mut recipes = vec![];
+
+    for name in names.iter().filter(|name| name.ends_with(".json")) {
+        let recipe: MealieRecipe = serde_json::from_slice(&read_entry(archive, name)?)
+            .with_context(|| format!("Reading {name}"))?;
+
+        // The photo sits beside the JSON, in whichever format Mealie converted it to
+        let dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let image_name = names
+            .iter()
+            .find(|other| other.starts_with(&format!("{dir}/images/original.")));
+
+        let image = match image_name {
+            Some(image_name) => Image::from_bytes(read_entry(archive, image_name)?),
+            None => None,
+        };
+
+        recipes.push(recipe.into_portable(image));
+    }
+
+    Ok(recipes)
+}
+
+/// What Mealie needs to import a recipe, in the shape of its API.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MealieExport<'a> {
+    name: &'a str,
+    slug: &'a str,
+    recipe_yield: Option<&'a str>,
+    prep_time: Option<String>,
+    perform_time: Option<String>,
+    #[serde(rename = "orgURL")]
+    org_url: Option<&'a str>,
+    recipe_ingredient: Vec<Value>,
+    recipe_instructions: Vec<Value>,
+    notes: Vec<Value>,
+    tags: Vec<Value>,
+}
+
+pub(super) fn write(recipes: &[PortableRecipe]) -> anyhow::Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default();
+    let mut names = FileNames::default();
+
+    for recipe in recipes {
+        let slug = names.unique(&recipe.title);
+
+        let export = MealieExport {
+            name: &recipe.title,
+            slug: &slug,
+            recipe_yield: recipe.servings.as_deref(),
+            prep_time: recipe
+                .prep_minutes
+                .map(|minutes| format!("{minutes} minutes")),
+            perform_time: recipe
+                .cook_minutes
+                .map(|minutes| format!("{minutes} minutes")),
+            org_url: recipe.source_url.as_deref(),
+            recipe_ingredient: recipe
+                .ingredients
+                .iter()
+                .map(|ingredient| json!({ "note": ingredient, "display": ingredient }))
+                .collect(),
+            recipe_instructions: recipe
+                .instructions
+                .iter()
+                .map(|text| json!({ "text": text }))
+                .collect(),
+            notes: recipe
+                .notes
+                .iter()
+                .map(|text| json!({ "title": "", "text": text }))
+                .collect(),
+            tags: recipe
+                .tags
+                .iter()
+                .map(|tag| json!({ "name": tag, "slug": crate::export::slugify(tag) }))
+                .collect(),
+        };
+
+        zip.start_file(format!("recipes/{slug}/{slug}.json"), options)?;
+        zip.write_all(&serde_json::to_vec_pretty(&export)?)?;
+
+        if let Some(image) = &recipe.image {
+            zip.start_file(
+                format!("recipes/{slug}/images/original.{}", image.extension),
+                options,
+            )?;
+            zip.write_all(&image.data)?;
+        }
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
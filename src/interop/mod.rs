@@ -0,0 +1,628 @@
+//! Moving recipes between this app and other recipe managers: Paprika's `.paprikarecipes`,
+//! Mealie's recipe JSON and Tandoor's export zips. Each format is read into and written from a
+//! [`PortableRecipe`], which maps onto a row in `recipes`, the collections it's in (the other
+//! apps' categories, tags or keywords) and its photo.
+//!
+//! Unlike the reel imports nothing is downloaded or extracted, the recipes are saved as they are.
+
+use crate::auth::CurrentUser;
+use crate::cli::{ExportRecipesArgs, ImportRecipesArgs};
+use crate::collections;
+use crate::entities::prelude::{Collections, InstagramVideo, Recipes};
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::entities::{collection_recipes, collections as collection, recipes};
+use crate::error::{self, AppError};
+use crate::export;
+use crate::imports::{self, ZIP_MAGIC};
+use crate::jobs::JobContext;
+use crate::uploads;
+use anyhow::{anyhow, bail, Context};
+use axum::extract::{Multipart, Path};
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Redirect};
+use axum::Extension;
+use lazy_static::lazy_static;
+use regex::Regex;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect, Set,
+    TransactionTrait,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Seek};
+use utoipa::ToSchema;
+
+pub mod mealie;
+pub mod paprika;
+pub mod tandoor;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// Largest file read out of an export, a zip entry or a gzipped Paprika recipe. Photos are the
+/// biggest thing in one, this stops a small export from decompressing into something that fills
+/// the memory.
+const MAX_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+
+lazy_static! {
+    static ref ISO_DURATION: Regex =
+        Regex::new(r"(?i)^P(?:\d+D)?T?(?:(\d+)H)?(?:(\d+)M)?").expect("Failed to compile regex");
+    static ref DURATION_PART: Regex =
+        Regex::new(r"(?i)(\d+(?:\.\d+)?)\s*(hours?|hrs?|h|minutes?|mins?|m)\b")
+            .expect("Failed to compile regex");
+}
+
+/// The recipe managers we can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Manager {
+    Paprika,
+    Mealie,
+    Tandoor,
+}
+
+impl Manager {
+    /// Name of the exported file, in the extension the app expects to import.
+    fn file_name(self) -> &'static str {
+        match self {
+            Manager::Paprika => "recipes.paprikarecipes",
+            Manager::Mealie => "recipes-mealie.zip",
+            Manager::Tandoor => "recipes-tandoor.zip",
+        }
+    }
+}
+
+/// A recipe photo, its extension taken from the image data rather than any file name.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub data: Vec<u8>,
+    pub extension: &'static str,
+}
+
+impl Image {
+    /// `None` if `data` isn't a JPEG, PNG or WebP image.
+    pub fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        let extension = if data.starts_with(b"\xff\xd8\xff") {
+            "jpg"
+        } else if data.starts_with(b"\x89PNG") {
+            "png"
+        } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            "webp"
+        } else {
+            return None;
+        };
+
+        Some(Image { data, extension })
+    }
+}
+
+/// A recipe as the formats have it in common.
+#[derive(Debug, Clone, Default)]
+pub struct PortableRecipe {
+    pub title: String,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<String>,
+    pub notes: Option<String>,
+    pub servings: Option<String>,
+    pub prep_minutes: Option<i32>,
+    pub cook_minutes: Option<i32>,
+    pub source_url: Option<String>,
+    /// Names of the collections it's in.
+    pub tags: Vec<String>,
+    pub image: Option<Image>,
+}
+
+/// Non-empty lines of `text`, trimmed, for formats that keep lists as one string.
+pub(crate) fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `None` for missing or blank strings, which the formats use interchangeably.
+pub(crate) fn non_empty(text: Option<String>) -> Option<String> {
+    text.map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Minutes in a duration written by a person or another app, e.g. "1 hr 20 mins", "45 min",
+/// "PT1H30M" or just "20".
+pub(crate) fn parse_minutes(text: &str) -> Option<i32> {
+    let text = text.trim();
+
+    if let Some(captures) = ISO_DURATION.captures(text).filter(|c| c[0].len() > 2) {
+        let hours: i32 = captures
+            .get(1)
+            .map_or(0, |h| h.as_str().parse().unwrap_or(0));
+        let minutes: i32 = captures
+            .get(2)
+            .map_or(0, |m| m.as_str().parse().unwrap_or(0));
+        return Some(hours * 60 + minutes).filter(|minutes| *minutes > 0);
+    }
+
+    if let Ok(minutes) = text.parse::<f64>() {
+        return Some(minutes.round() as i32).filter(|minutes| *minutes > 0);
+    }
+
+    let minutes: f64 = DURATION_PART
+        .captures_iter(text)
+        .map(|captures| {
+            let amount: f64 = captures[1].parse().unwrap_or(0.0);
+            if captures[2].to_lowercase().starts_with('h') {
+                amount * 60.0
+            } else {
+                amount
+            }
+        })
+        .sum();
+
+    Some(minutes.round() as i32).filter(|minutes| *minutes > 0)
+}
+
+/// Read every recipe in a file exported from any of the supported apps, working out which from
+/// its contents.
+pub fn read(bytes: &[u8]) -> anyhow::Result<Vec<PortableRecipe>> {
+    if bytes.starts_with(ZIP_MAGIC) {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Not a zip file")?;
+        let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+
+        if names.iter().any(|name| name.ends_with(".paprikarecipe")) {
+            return paprika::read_archive(&mut archive);
+        }
+
+        if names
+            .iter()
+            .any(|name| name.ends_with(".zip") || name.ends_with("recipe.json"))
+        {
+            return tandoor::read_archive(&mut archive);
+        }
+
+        if names.iter().any(|name| name.ends_with(".json")) {
+            return mealie::read_archive(&mut archive);
+        }
+
+        bail!("No Paprika, Mealie or Tandoor recipes in the zip");
+    }
+
+    if bytes.starts_with(GZIP_MAGIC) {
+        return Ok(vec![paprika::read_recipe(bytes)?]);
+    }
+
+    let value: serde_json::Value =
+        serde_json::from_slice(bytes).context("Not a zip or JSON file")?;
+
+    if value.get("steps").is_some() {
+        Ok(vec![tandoor::from_json(value)?])
+    } else {
+        mealie::from_json(value)
+    }
+}
+
+/// Write `recipes` as a file `manager` can import.
+pub fn write(manager: Manager, recipes: &[PortableRecipe]) -> anyhow::Result<Vec<u8>> {
+    match manager {
+        Manager::Paprika => paprika::write(recipes),
+        Manager::Mealie => mealie::write(recipes),
+        Manager::Tandoor => tandoor::write(recipes),
+    }
+}
+
+/// File names for the recipes in an export, from their titles, numbered when titles repeat.
+#[derive(Default)]
+pub(crate) struct FileNames(HashSet<String>);
+
+impl FileNames {
+    pub(crate) fn unique(&mut self, title: &str) -> String {
+        let mut base = export::slugify(title);
+        if base.is_empty() {
+            base = "recipe".to_string();
+        }
+
+        let mut name = base.clone();
+        for n in 2.. {
+            if self.0.insert(name.clone()) {
+                break;
+            }
+            name = format!("{base}-{n}");
+        }
+
+        name
+    }
+}
+
+/// Read a whole entry of a zip.
+pub(crate) fn read_entry<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let file = archive.by_name(name)?;
+
+    // The size in the header is only what the zip claims, so the read is capped as well
+    if file.size() > MAX_ENTRY_BYTES {
+        bail!("{name} is too large");
+    }
+
+    let mut bytes = vec![];
+    file.take(MAX_ENTRY_BYTES + 1).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 > MAX_ENTRY_BYTES {
+        bail!("{name} is too large");
+    }
+
+    Ok(bytes)
+}
+
+/// How an import went.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Already in the library, with the same title and ingredients.
+    pub duplicates: usize,
+}
+
+/// Save `recipes` to the user's household, with their photos and collections. Recipes the
+/// household already has are skipped, so importing the same export twice is harmless.
+pub async fn save(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    user: &CurrentUser,
+    recipes: Vec<PortableRecipe>,
+) -> anyhow::Result<ImportSummary> {
+    let txn = db.begin().await?;
+    let mut summary = ImportSummary::default();
+    let mut images = vec![];
+
+    for recipe in recipes {
+        // Compared here rather than in the query as ours can be null where the import has none
+        let duplicate = Recipes::find()
+            .filter(recipes::Column::HouseholdId.eq(user.household_id))
+            .filter(recipes::Column::Title.eq(&recipe.title))
+            .all(&txn)
+            .await?
+            .into_iter()
+            .any(|existing| existing.ingredients.unwrap_or_default() == recipe.ingredients);
+
+        if duplicate {
+            summary.duplicates += 1;
+            continue;
+        }
+
+        let mut model = recipes::ActiveModel {
+            title: Set(Some(recipe.title)),
+            ingredients: Set(Some(recipe.ingredients)),
+            instructions: Set(Some(recipe.instructions)),
+            notes: Set(recipe.notes),
+            servings: Set(recipe.servings),
+            prep_minutes: Set(recipe.prep_minutes),
+            cook_minutes: Set(recipe.cook_minutes),
+            source_url: Set(recipe.source_url),
+            owner_id: Set(Some(user.id)),
+            household_id: Set(Some(user.household_id)),
+
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        for tag in &recipe.tags {
+            let collection = collections::find_or_create(&txn, user.household_id, tag).await?;
+            collections::add_recipe(&txn, collection.id, model.id).await?;
+        }
+
+        if let Some(image) = recipe.image {
+            let file_name = format!("{}.{}", model.id, image.extension);
+
+            let mut active: recipes::ActiveModel = model.into();
+            active.image = Set(Some(file_name.clone()));
+            model = active.update(&txn).await?;
+
+            images.push((file_name, image.data));
+        }
+
+        tracing::debug!("Imported recipe {}", model.id);
+        summary.imported += 1;
+    }
+
+    // Written last so a failed import doesn't leave photos behind for recipes that don't exist
+    let image_dir = context.recipe_image_dir();
+    if !images.is_empty() {
+        std::fs::create_dir_all(&image_dir)?;
+    }
+    for (file_name, data) in images {
+        std::fs::write(image_dir.join(file_name), data)?;
+    }
+
+    txn.commit().await?;
+
+    Ok(summary)
+}
+
+/// Every recipe in the household, with its collections and photo, the reel's thumbnail standing
+/// in for recipes extracted from one.
+pub async fn load(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    household_id: i32,
+) -> anyhow::Result<Vec<PortableRecipe>> {
+    let rows = Recipes::find()
+        .filter(recipes::Column::HouseholdId.eq(household_id))
+        .find_also_related(InstagramVideo)
+        .all(db)
+        .await?;
+
+    let memberships: Vec<(i32, String)> = Collections::find()
+        .select_only()
+        .column(collection_recipes::Column::RecipeId)
+        .column(collection::Column::Name)
+        .inner_join(crate::entities::prelude::CollectionRecipes)
+        .filter(collection::Column::HouseholdId.eq(household_id))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    for (recipe_id, name) in memberships {
+        tags.entry(recipe_id).or_default().push(name);
+    }
+
+    let mut recipes = vec![];
+
+    for (recipe, video) in rows {
+        let image_path = match (&recipe.image, &video) {
+            (Some(image), _) => Some(context.recipe_image_dir().join(image)),
+            (None, Some(video)) => Some(context.thumbnail_path(&video.instagram_id)),
+            (None, None) => None,
+        };
+
+        let image = image_path
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(Image::from_bytes);
+
+        let video_url = video.as_ref().and_then(uploads::source_url);
+        let source_url = recipe.source_url.or(video_url.map(str::to_string));
+
+        recipes.push(PortableRecipe {
+            title: recipe
+                .title
+                .unwrap_or_else(|| "Untitled recipe".to_string()),
+            ingredients: recipe.ingredients.unwrap_or_default(),
+            instructions: recipe.instructions.unwrap_or_default(),
+            notes: recipe.notes,
+            servings: recipe.servings,
+            prep_minutes: recipe.prep_minutes,
+            cook_minutes: recipe.cook_minutes,
+            source_url,
+            tags: tags.remove(&recipe.id).unwrap_or_default(),
+            image,
+        });
+    }
+
+    Ok(recipes)
+}
+
+/// The fields of the recipe import form.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct RecipeImportForm {
+    /// A Paprika `.paprikarecipes` or `.paprikarecipe` file, a Mealie recipe JSON file or zip of
+    /// them, or a Tandoor export zip.
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// Import recipes exported from Paprika, Mealie or Tandoor straight into the library.
+#[utoipa::path(
+    post,
+    path = "/imports/recipes",
+    tag = "recipes",
+    request_body(content_type = "multipart/form-data", content = inline(RecipeImportForm)),
+    responses(
+        (status = 303, description = "Imported, redirects to the recipe list"),
+        (status = 400, description = "Not an export from one of the supported apps"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
+pub async fn import_recipes(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(context): Extension<JobContext>,
+    mut multipart: Multipart,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Editor)?;
+
+    let mut recipes = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::BadRequest(e.into()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let bytes = field
+            .bytes()
+            .await
+            .map_err(|e| AppError::BadRequest(e.into()))?;
+
+        recipes = Some(read(&bytes).map_err(AppError::BadRequest)?);
+    }
+
+    let recipes = recipes.ok_or_else(|| AppError::BadRequest(anyhow!("No file uploaded")))?;
+    let summary = save(&db, &context, &user, recipes).await?;
+
+    tracing::info!(
+        "Imported {} recipes, skipped {} duplicates",
+        summary.imported,
+        summary.duplicates
+    );
+
+    Ok(Redirect::to(&format!(
+        "/recipes?imported={}",
+        summary.imported
+    )))
+}
+
+/// Every recipe in the household as a file Paprika, Mealie or Tandoor can import.
+#[utoipa::path(
+    get,
+    path = "/recipes/export/{app}",
+    tag = "recipes",
+    params(("app" = Manager, Path, description = "The app to export for")),
+    responses(
+        (status = 200, description = "The export", content_type = "application/zip"),
+    )
+)]
+pub async fn export_recipes(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(context): Extension<JobContext>,
+    Path((manager,)): Path<(Manager,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let recipes = load(&db, &context, user.household_id).await?;
+    let bytes = write(manager, &recipes)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", manager.file_name()),
+            ),
+        ],
+        bytes,
+    ))
+}
+
+/// Backs the `import-recipes` subcommand.
+pub async fn import_file(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    args: &ImportRecipesArgs,
+) -> anyhow::Result<()> {
+    let user = imports::find_user(db, &args.username).await?;
+
+    user.require(HouseholdRole::Editor)
+        .map_err(|_| anyhow!("{} can't add recipes to their household", user.username))?;
+
+    let bytes = std::fs::read(&args.path).with_context(|| format!("Reading {:?}", args.path))?;
+    let summary = save(db, context, &user, read(&bytes)?).await?;
+
+    tracing::info!(
+        "Imported {} recipes, skipped {} already in the library",
+        summary.imported,
+        summary.duplicates
+    );
+
+    Ok(())
+}
+
+/// Backs the `export-recipes` subcommand.
+pub async fn export_file(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    args: &ExportRecipesArgs,
+) -> anyhow::Result<()> {
+    let user = imports::find_user(db, &args.username).await?;
+
+    let recipes = load(db, context, user.household_id).await?;
+    std::fs::write(&args.output, write(args.app, &recipes)?)
+        .with_context(|| format!("Writing {:?}", args.output))?;
+
+    tracing::info!("Exported {} recipes to {:?}", recipes.len(), args.output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_minutes("PT1H30M"), Some(90));
+        assert_eq!(parse_minutes("PT45M"), Some(45));
+        assert_eq!(parse_minutes("P0DT2H"), Some(120));
+        assert_eq!(parse_minutes("1 hr 20 mins"), Some(80));
+        assert_eq!(parse_minutes("1.5 hours"), Some(90));
+        assert_eq!(parse_minutes(" 20 "), Some(20));
+        assert_eq!(parse_minutes("PT0M"), None);
+        assert_eq!(parse_minutes("overnight"), None);
+    }
+
+    fn recipe() -> PortableRecipe {
+        PortableRecipe {
+            title: "Tomato pasta".to_string(),
+            ingredients: vec![
+                "200 g spaghetti".to_string(),
+                "2 tomatoes, chopped".to_string(),
+                "Salt and pepper".to_string(),
+            ],
+            instructions: vec![
+                "Boil the spaghetti.".to_string(),
+                "Toss with the tomatoes.".to_string(),
+            ],
+            notes: Some("Best eaten fresh.".to_string()),
+            servings: Some("4 people".to_string()),
+            prep_minutes: Some(15),
+            cook_minutes: Some(80),
+            source_url: Some("https://example.com/tomato-pasta".to_string()),
+            tags: vec!["Dinner".to_string()],
+            image: Image::from_bytes(b"\x89PNG\r\n\x1a\nnot really a png".to_vec()),
+        }
+    }
+
+    fn round_trip(manager: Manager) {
+        let original = recipe();
+        let read = read(&write(manager, &[original.clone(), original.clone()]).unwrap()).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for recipe in read {
+            assert_eq!(recipe.title, original.title);
+            assert_eq!(recipe.ingredients, original.ingredients);
+            assert_eq!(recipe.instructions, original.instructions);
+            assert_eq!(recipe.notes, original.notes);
+            assert_eq!(recipe.servings, original.servings);
+            assert_eq!(recipe.prep_minutes, original.prep_minutes);
+            assert_eq!(recipe.cook_minutes, original.cook_minutes);
+            assert_eq!(recipe.source_url, original.source_url);
+            assert_eq!(recipe.tags, original.tags);
+
+            let image = recipe.image.expect("image");
+            assert_eq!(image.extension, "png");
+            assert_eq!(image.data, original.image.as_ref().unwrap().data);
+        }
+    }
+
+    #[test]
+    fn paprika_round_trip() {
+        round_trip(Manager::Paprika);
+    }
+
+    #[test]
+    fn mealie_round_trip() {
+        round_trip(Manager::Mealie);
+    }
+
+    #[test]
+    fn tandoor_round_trip() {
+        round_trip(Manager::Tandoor);
+    }
+
+    #[test]
+    fn refuses_oversized_entries() {
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("big.json", options).unwrap();
+        std::io::Write::write_all(&mut zip, &vec![b' '; MAX_ENTRY_BYTES as usize + 1]).unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert!(read_entry(&mut archive, "big.json").is_err());
+    }
+}
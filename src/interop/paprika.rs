@@ -0,0 +1,136 @@
+//! Paprika's `.paprikarecipes` export: a zip of `.paprikarecipe` files, each a gzipped JSON
+//! recipe with the photo inlined as base64.
+
+use super::{lines, non_empty, parse_minutes, read_entry, Image, PortableRecipe, MAX_ENTRY_BYTES};
+use anyhow::{bail, Context};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::Url;
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Cursor, Read, Seek, Write};
+use zip::write::FileOptions;
+
+/// The fields of a Paprika recipe we use, Paprika leaves any of them out or empty.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PaprikaRecipe {
+    uid: Option<String>,
+    name: Option<String>,
+    ingredients: Option<String>,
+    directions: Option<String>,
+    notes: Option<String>,
+    servings: Option<String>,
+    prep_time: Option<String>,
+    cook_time: Option<String>,
+    source: Option<String>,
+    source_url: Option<String>,
+    categories: Vec<String>,
+    photo_data: Option<String>,
+    hash: Option<String>,
+    created: Option<String>,
+}
+
+pub(super) fn read_archive<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> anyhow::Result<Vec<PortableRecipe>> {
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.ends_with(".paprikarecipe"))
+        .map(str::to_string)
+        .collect();
+
+    names
+        .iter()
+        .map(|name| {
+            read_recipe(&read_entry(archive, name)?).with_context(|| format!("Reading {name}"))
+        })
+        .collect()
+}
+
+/// Read a single gzipped `.paprikarecipe`.
+pub(super) fn read_recipe(bytes: &[u8]) -> anyhow::Result<PortableRecipe> {
+    let mut json = vec![];
+    GzDecoder::new(bytes)
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_end(&mut json)
+        .context("Not a Paprika recipe")?;
+
+    if json.len() as u64 > MAX_ENTRY_BYTES {
+        bail!("Recipe is too large");
+    }
+
+    let recipe: PaprikaRecipe = serde_json::from_slice(&json)?;
+
+    let image = recipe
+        .photo_data
+        .and_then(|data| BASE64.decode(data).ok())
+        .and_then(Image::from_bytes);
+
+    Ok(PortableRecipe {
+        title: non_empty(recipe.name).unwrap_or_else(|| "Untitled recipe".to_string()),
+        ingredients: lines(recipe.ingredients.as_deref().unwrap_or_default()),
+        instructions: lines(recipe.directions.as_deref().unwrap_or_default()),
+        notes: non_empty(recipe.notes),
+        servings: non_empty(recipe.servings),
+        prep_minutes: recipe.prep_time.as_deref().and_then(parse_minutes),
+        cook_minutes: recipe.cook_time.as_deref().and_then(parse_minutes),
+        source_url: non_empty(recipe.source_url),
+        tags: recipe.categories,
+        image,
+    })
+}
+
+fn to_paprika(recipe: &PortableRecipe) -> PaprikaRecipe {
+    let mut paprika = PaprikaRecipe {
+        uid: Some(Uuid::new_v4().to_string().to_uppercase()),
+        name: Some(recipe.title.clone()),
+        ingredients: Some(recipe.ingredients.join("\n")),
+        directions: Some(recipe.instructions.join("\n\n")),
+        notes: recipe.notes.clone(),
+        servings: recipe.servings.clone(),
+        prep_time: recipe.prep_minutes.map(|minutes| format!("{minutes} mins")),
+        cook_time: recipe.cook_minutes.map(|minutes| format!("{minutes} mins")),
+        source_url: recipe.source_url.clone(),
+        source: recipe
+            .source_url
+            .as_deref()
+            .and_then(|url| Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(str::to_string)),
+        categories: recipe.tags.clone(),
+        photo_data: recipe
+            .image
+            .as_ref()
+            .map(|image| BASE64.encode(&image.data)),
+        hash: None,
+        created: Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+    };
+
+    // Paprika uses the hash to tell whether a recipe changed when syncing
+    let content = serde_json::to_vec(&paprika).unwrap_or_default();
+    paprika.hash = Some(hex::encode(Sha256::digest(content)).to_uppercase());
+
+    paprika
+}
+
+pub(super) fn write(recipes: &[PortableRecipe]) -> anyhow::Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    // The entries are gzipped already
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut names = super::FileNames::default();
+
+    for recipe in recipes {
+        let mut gzip = GzEncoder::new(vec![], Compression::default());
+        gzip.write_all(&serde_json::to_vec(&to_paprika(recipe))?)?;
+
+        let name = names.unique(&recipe.title);
+        zip.start_file(format!("{name}.paprikarecipe"), options)?;
+        zip.write_all(&gzip.finish()?)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,280 @@
+//! Tandoor's export: a zip of zips, one per recipe, each with a `recipe.json` and the photo as
+//! `image.<ext>`. A single `recipe.json` on its own is read too.
+//!
+//! Tandoor splits ingredients into amount, unit and food, and attaches them to steps. Going in
+//! they're joined back into lines, going out they're split with the same parser as the Cooklang
+//! export and all attached to the first step.
+
+use super::{lines, non_empty, read_entry, FileNames, Image, PortableRecipe};
+use crate::export::Ingredient;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Cursor, Read, Seek, Write};
+use zip::write::FileOptions;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TandoorIngredient {
+    food: Option<Named>,
+    unit: Option<Named>,
+    amount: f64,
+    note: Option<String>,
+    order: usize,
+    is_header: bool,
+    no_amount: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Step {
+    name: Option<String>,
+    instruction: Option<String>,
+    ingredients: Vec<TandoorIngredient>,
+    order: usize,
+    show_ingredients_table: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TandoorRecipe {
+    name: String,
+    description: Option<String>,
+    keywords: Vec<Named>,
+    steps: Vec<Step>,
+    working_time: i32,
+    waiting_time: i32,
+    internal: bool,
+    servings: i32,
+    servings_text: Option<String>,
+    source_url: Option<String>,
+}
+
+impl TandoorIngredient {
+    fn into_text(self) -> Option<String> {
+        if self.is_header {
+            return non_empty(self.note);
+        }
+
+        let mut parts = vec![];
+        if !self.no_amount && self.amount > 0.0 {
+            parts.push(self.amount.to_string());
+        }
+        parts.extend(self.unit.map(|unit| unit.name));
+        parts.extend(self.food.map(|food| food.name));
+
+        let text = parts.join(" ");
+        match non_empty(self.note) {
+            Some(note) if text.is_empty() => Some(note),
+            Some(note) => Some(format!("{text}, {note}")),
+            None => non_empty(Some(text)),
+        }
+    }
+
+    fn from_line(line: &str, order: usize) -> Self {
+        let (main, note) = match line.find([',', '(']) {
+            Some(i) => (
+                &line[..i],
+                line[i..]
+                    .trim_start_matches([',', ' ', '('])
+                    .trim_end_matches(')'),
+            ),
+            None => (line, ""),
+        };
+        let note = non_empty(Some(note.to_string()));
+
+        let parsed = Ingredient::parse(main);
+
+        match parsed.amount_value() {
+            Some(amount) if !parsed.names.is_empty() => TandoorIngredient {
+                food: Some(Named {
+                    name: parsed.names.join(" and "),
+                }),
+                unit: parsed.unit.map(|name| Named { name }),
+                amount,
+                note,
+                order,
+                ..Default::default()
+            },
+
+            // Something like "salt to taste", the whole line is the food
+            _ => TandoorIngredient {
+                food: Some(Named {
+                    name: main.trim().to_string(),
+                }),
+                note,
+                order,
+                no_amount: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl TandoorRecipe {
+    fn into_portable(self, image: Option<Image>) -> PortableRecipe {
+        let mut ingredients = vec![];
+        let mut instructions = vec![];
+
+        for step in self.steps {
+            ingredients.extend(
+                step.ingredients
+                    .into_iter()
+                    .filter_map(TandoorIngredient::into_text),
+            );
+            instructions.extend(lines(step.instruction.as_deref().unwrap_or_default()));
+        }
+
+        let servings = non_empty(self.servings_text)
+            .map(|text| format!("{} {text}", self.servings))
+            .or((self.servings > 0).then(|| self.servings.to_string()));
+
+        PortableRecipe {
+            title: non_empty(Some(self.name)).unwrap_or_else(|| "Untitled recipe".to_string()),
+            ingredients,
+            instructions,
+            notes: non_empty(self.description),
+            servings,
+            prep_minutes: Some(self.working_time).filter(|minutes| *minutes > 0),
+            cook_minutes: Some(self.waiting_time).filter(|minutes| *minutes > 0),
+            source_url: non_empty(self.source_url),
+            tags: self
+                .keywords
+                .into_iter()
+                .map(|keyword| keyword.name)
+                .collect(),
+            image,
+        }
+    }
+
+    fn from_portable(recipe: &PortableRecipe) -> Self {
+        // "4 people" keeps its number as servings and the rest as the text Tandoor shows after it,
+        // none is left as 0 so it doesn't come back as 1
+        let servings = recipe.servings.as_deref().unwrap_or_default().trim();
+        let (number, text) = servings.split_once(' ').unwrap_or((servings, ""));
+        let (servings, servings_text) = match number.parse() {
+            Ok(number) => (number, non_empty(Some(text.to_string()))),
+            Err(_) if servings.is_empty() => (0, None),
+            Err(_) => (1, Some(servings.to_string())),
+        };
+
+        let ingredients = recipe
+            .ingredients
+            .iter()
+            .enumerate()
+            .map(|(order, line)| TandoorIngredient::from_line(line, order))
+            .collect();
+
+        let mut steps: Vec<Step> = recipe
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(order, instruction)| Step {
+                instruction: Some(instruction.clone()),
+                order,
+                ..Default::default()
+            })
+            .collect();
+
+        if steps.is_empty() {
+            steps.push(Step::default());
+        }
+        steps[0].ingredients = ingredients;
+        steps[0].show_ingredients_table = true;
+
+        TandoorRecipe {
+            name: recipe.title.clone(),
+            description: recipe.notes.clone(),
+            keywords: recipe
+                .tags
+                .iter()
+                .map(|tag| Named { name: tag.clone() })
+                .collect(),
+            steps,
+            working_time: recipe.prep_minutes.unwrap_or_default(),
+            waiting_time: recipe.cook_minutes.unwrap_or_default(),
+            internal: true,
+            servings,
+            servings_text,
+            source_url: recipe.source_url.clone(),
+        }
+    }
+}
+
+/// A lone `recipe.json`.
+pub(super) fn from_json(value: Value) -> anyhow::Result<PortableRecipe> {
+    let recipe: TandoorRecipe = serde_json::from_value(value).context("Not a Tandoor recipe")?;
+    Ok(recipe.into_portable(None))
+}
+
+/// One recipe's zip, or the export's own files when a single recipe was exported unwrapped.
+fn read_recipe<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> anyhow::Result<PortableRecipe> {
+    let recipe: TandoorRecipe = serde_json::from_slice(&read_entry(archive, "recipe.json")?)?;
+
+    let image_name = archive
+        .file_names()
+        .find(|name| name.starts_with("image."))
+        .map(str::to_string);
+
+    let image = match image_name {
+        Some(name) => Image::from_bytes(read_entry(archive, &name)?),
+        None => None,
+    };
+
+    Ok(recipe.into_portable(image))
+}
+
+pub(super) fn read_archive<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+) -> anyhow::Result<Vec<PortableRecipe>> {
+    if archive.file_names().any(|name| name == "recipe.json") {
+        return Ok(vec![read_recipe(archive)?]);
+    }
+
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.ends_with(".zip"))
+        .map(str::to_string)
+        .collect();
+
+    if names.is_empty() {
+        bail!("No Tandoor recipes in the zip");
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            let mut inner = zip::ZipArchive::new(Cursor::new(read_entry(archive, name)?))?;
+            read_recipe(&mut inner).with_context(|| format!("Reading {name}"))
+        })
+        .collect()
+}
+
+pub(super) fn write(recipes: &[PortableRecipe]) -> anyhow::Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default();
+    let mut names = FileNames::default();
+
+    for recipe in recipes {
+        let mut inner = zip::ZipWriter::new(Cursor::new(vec![]));
+
+        inner.start_file("recipe.json", options)?;
+        inner.write_all(&serde_json::to_vec(&TandoorRecipe::from_portable(recipe))?)?;
+
+        if let Some(image) = &recipe.image {
+            inner.start_file(format!("image.{}", image.extension), options)?;
+            inner.write_all(&image.data)?;
+        }
+
+        zip.start_file(format!("{}.zip", names.unique(&recipe.title)), options)?;
+        zip.write_all(&inner.finish()?.into_inner())?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
@@ -0,0 +1,217 @@
+//! Text burned into the video, which is often the only place a reel gives its quantities. Frames
+//! are sampled at scene changes with ffmpeg, read with the `tesseract` CLI, and the lines that
+//! repeat from frame to frame are dropped.
+//!
+//! Reading it is best effort, a video whose text can't be read still gets its recipes extracted
+//! from the description and transcript.
+
+use crate::entities::instagram_video;
+use crate::jobs::extract_transcript::RecipeDestination;
+use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
+use anyhow::bail;
+use async_trait::async_trait;
+use fang::{AsyncQueueable, AsyncRunnable, Deserialize, FangError, Serialize};
+use sea_orm::{EntityTrait, Set};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::process::Command;
+
+/// How different a frame has to be from the one before to count as a new scene, from 0 to 1.
+const SCENE_THRESHOLD: &str = "0.3";
+
+/// Most frames read per video, captions rarely change more often than this.
+const MAX_FRAMES: &str = "40";
+
+pub struct OnScreenText;
+
+impl OnScreenText {
+    /// The distinct lines of text shown in the video, in the order they first appear. `None` if
+    /// there aren't any.
+    #[tracing::instrument]
+    pub async fn extract(video_path: &Path) -> anyhow::Result<Option<String>> {
+        tracing::info!("Reading on-screen text");
+        let frames_dir = TempDir::new()?;
+        Self::sample_frames(video_path, frames_dir.path()).await?;
+
+        let mut frames: Vec<_> = std::fs::read_dir(frames_dir.path())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        frames.sort();
+
+        Self::read_images(&frames).await
+    }
+
+    /// The distinct lines of text in `images`, in order, e.g. the recipe card photographed in a
+    /// carousel post. `None` if there aren't any.
+    pub async fn read_images(images: &[PathBuf]) -> anyhow::Result<Option<String>> {
+        let mut seen = HashSet::new();
+        let mut lines = vec![];
+
+        for image in images {
+            for line in Self::read_frame(image).await?.lines() {
+                let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+
+                // Stray marks on busy frames come out as a character or two of noise
+                let key: String = line
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect();
+
+                if key.len() >= 3 && seen.insert(key) {
+                    lines.push(line);
+                }
+            }
+        }
+
+        tracing::info!("Read {} lines of on-screen text", lines.len());
+        Ok((!lines.is_empty()).then(|| lines.join("\n")))
+    }
+
+    /// The first frame and one per scene change, as PNGs in `dir`.
+    async fn sample_frames(video_path: &Path, dir: &Path) -> anyhow::Result<()> {
+        let output = telemetry::time_stage(
+            "sample_frames",
+            Command::new("ffmpeg")
+                .arg("-i")
+                .arg(video_path)
+                .args([
+                    "-vf",
+                    &format!("select='eq(n,0)+gt(scene,{SCENE_THRESHOLD})'"),
+                ])
+                .args(["-vsync", "vfr", "-frames:v", MAX_FRAMES])
+                .arg(dir.join("frame-%03d.png"))
+                .output(),
+        )
+        .await?;
+
+        if !output.status.success() {
+            bail!(
+                "Error occurred when sampling frames {:?}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+        }
+
+        Ok(())
+    }
+
+    async fn read_frame(frame: &Path) -> anyhow::Result<String> {
+        // Sparse text mode, captions are scattered over the frame rather than laid out as a page
+        let output = telemetry::time_stage(
+            "ocr",
+            Command::new("tesseract")
+                .arg(frame)
+                .args(["stdout", "--psm", "11"])
+                .output(),
+        )
+        .await?;
+
+        if !output.status.success() {
+            bail!(
+                "Error occurred when reading frame {:?}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct ExtractOnScreenTextJob {
+    pub video_id: i32,
+    pub reel_id: String,
+
+    /// Extract recipes into this household's library once read, with or without any text.
+    #[serde(default)]
+    pub extract_recipes_for: Option<RecipeDestination>,
+
+    #[serde(default)]
+    pub trace_context: TraceContext,
+}
+
+impl ExtractOnScreenTextJob {
+    pub fn new(
+        video_id: i32,
+        reel_id: String,
+        extract_recipes_for: Option<RecipeDestination>,
+    ) -> Self {
+        Self {
+            video_id,
+            reel_id,
+            extract_recipes_for,
+            trace_context: TraceContext::current(),
+        }
+    }
+
+    async fn exec(&self, context: &JobContext) -> anyhow::Result<()> {
+        let video_path = context.video_path(&self.reel_id);
+        let on_screen_text = OnScreenText::extract(&video_path).await?;
+
+        instagram_video::Entity::update(instagram_video::ActiveModel {
+            id: Set(self.video_id),
+            on_screen_text: Set(on_screen_text),
+            ..Default::default()
+        })
+        .exec(&context.db)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for ExtractOnScreenTextJob {
+    #[tracing::instrument(skip(queue))]
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
+        let context = JOB_CONTEXT.get().ok_or(FangError {
+            description: "Failed to read context".to_string(),
+        })?;
+
+        // Not retried, the recipes shouldn't wait on text that may never be readable
+        match self.exec(context).await {
+            Ok(()) => telemetry::record_job_outcome(
+                crate::jobs::EXTRACT_ON_SCREEN_TEXT_TASK_TYPE,
+                "success",
+            ),
+
+            Err(e) => {
+                tracing::warn!("Failed to read on-screen text: {e:?}");
+                telemetry::record_job_outcome(
+                    crate::jobs::EXTRACT_ON_SCREEN_TEXT_TASK_TYPE,
+                    "error",
+                );
+            }
+        }
+
+        if let Some(destination) = self.extract_recipes_for {
+            let job = crate::jobs::llm_extract_details::LLmExtractDetailsJob::new(
+                self.video_id,
+                Some(destination.owner_id),
+                Some(destination.household_id),
+            );
+            crate::jobs::enqueue(queue, &context.db, &job).await?;
+        }
+
+        Ok(())
+    }
+
+    fn task_type(&self) -> String {
+        crate::jobs::EXTRACT_ON_SCREEN_TEXT_TASK_TYPE.to_string()
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn max_retries(&self) -> i32 {
+        0
+    }
+}
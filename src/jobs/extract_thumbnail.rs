@@ -0,0 +1,197 @@
+//! Poster frames for the recipe cards, saved next to each video as `<reel id>.jpg`. Reels get
+//! the thumbnail Instagram shows when yt-dlp can fetch it, anything else a frame from the video,
+//! and image posts their first image.
+
+use crate::entities::instagram_video;
+use crate::entities::prelude::InstagramVideo;
+use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use fang::{AsyncQueueable, AsyncRunnable, Deserialize, FangError, Serialize};
+use sea_orm::{DatabaseConnection, EntityTrait, QuerySelect};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use tokio::process::Command;
+
+/// Seconds into the video to take the frame from, the very first is often black or a title card.
+const POSTER_OFFSET: &str = "1";
+
+/// Width of the stored frame, plenty for a card.
+const POSTER_WIDTH: u32 = 480;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct ExtractThumbnailJob {
+    pub video_id: i32,
+    pub reel_id: String,
+
+    #[serde(default)]
+    pub trace_context: TraceContext,
+}
+
+impl ExtractThumbnailJob {
+    pub fn new(video_id: i32, reel_id: String) -> Self {
+        Self {
+            video_id,
+            reel_id,
+            trace_context: TraceContext::current(),
+        }
+    }
+
+    async fn exec(&self, context: &JobContext) -> anyhow::Result<()> {
+        let thumbnail_path = context.thumbnail_path(&self.reel_id);
+
+        if thumbnail_path.exists() {
+            tracing::info!("Thumbnail already extracted");
+            return Ok(());
+        }
+
+        let video_path = context.video_path(&self.reel_id);
+
+        let source = if video_path.exists() {
+            video_path
+        } else {
+            first_image(&context.post_dir(&self.reel_id))
+                .ok_or_else(|| anyhow!("No video file or images for {}", self.reel_id))?
+        };
+
+        // Clips shorter than the offset produce no frame, nor do images, so fall back to the first
+        for offset in [POSTER_OFFSET, "0"] {
+            extract_frame(&source, &thumbnail_path, offset).await?;
+
+            if thumbnail_path.exists() {
+                return Ok(());
+            }
+        }
+
+        bail!("ffmpeg produced no frame for {}", self.reel_id)
+    }
+}
+
+/// The first of a post's images, they're named in carousel order.
+fn first_image(post_dir: &Path) -> Option<PathBuf> {
+    let mut images: Vec<_> = std::fs::read_dir(post_dir)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+
+    images.sort();
+    images.into_iter().next()
+}
+
+/// Write the frame at `offset` to `thumbnail_path`, or nothing if there's no frame there. ffmpeg
+/// writes into a folder beside it first, so the thumbnail is never served half written.
+async fn extract_frame(
+    video_path: &Path,
+    thumbnail_path: &Path,
+    offset: &str,
+) -> anyhow::Result<()> {
+    let dir = thumbnail_path
+        .parent()
+        .ok_or_else(|| anyhow!("No folder for {thumbnail_path:?}"))?;
+    let temp_dir = TempDir::new_in(dir)?;
+    let temp_thumbnail = temp_dir.path().join("thumbnail.jpg");
+
+    let output = telemetry::time_stage(
+        "extract_thumbnail",
+        Command::new("ffmpeg")
+            .args(["-y", "-ss", offset, "-i"])
+            .arg(video_path)
+            .args(["-frames:v", "1", "-q:v", "4"])
+            .args(["-vf", &format!("scale={POSTER_WIDTH}:-2")])
+            .arg(&temp_thumbnail)
+            .output(),
+    )
+    .await?;
+
+    if !output.status.success() {
+        bail!(
+            "Error occurred when extracting thumbnail {:?}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+    }
+
+    if temp_thumbnail.exists() {
+        std::fs::rename(&temp_thumbnail, thumbnail_path)?;
+    }
+
+    Ok(())
+}
+
+/// Queue a thumbnail for every video in the reel directory that doesn't have one yet, for videos
+/// fetched before thumbnails were extracted.
+pub async fn queue_missing(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    queue: &mut dyn AsyncQueueable,
+) -> anyhow::Result<()> {
+    let videos: Vec<(i32, String)> = InstagramVideo::find()
+        .select_only()
+        .columns([
+            instagram_video::Column::Id,
+            instagram_video::Column::InstagramId,
+        ])
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut queued = 0;
+
+    for (video_id, reel_id) in videos {
+        let has_media =
+            context.video_path(&reel_id).exists() || context.post_dir(&reel_id).is_dir();
+
+        if has_media && !context.thumbnail_path(&reel_id).exists() {
+            let job = ExtractThumbnailJob::new(video_id, reel_id);
+            crate::jobs::enqueue(queue, db, &job)
+                .await
+                .map_err(|e| anyhow::anyhow!(e.description))?;
+            queued += 1;
+        }
+    }
+
+    tracing::info!("Queued {queued} thumbnails");
+    Ok(())
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for ExtractThumbnailJob {
+    #[tracing::instrument(skip(_queue))]
+    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
+        let context = JOB_CONTEXT.get().ok_or(FangError {
+            description: "Failed to read context".to_string(),
+        })?;
+
+        self.exec(context).await.map_err(|e| {
+            tracing::error!("{e:?}");
+            telemetry::record_job_outcome(crate::jobs::EXTRACT_THUMBNAIL_TASK_TYPE, "error");
+            FangError {
+                description: e.to_string(),
+            }
+        })?;
+
+        telemetry::record_job_outcome(crate::jobs::EXTRACT_THUMBNAIL_TASK_TYPE, "success");
+        Ok(())
+    }
+
+    fn task_type(&self) -> String {
+        crate::jobs::EXTRACT_THUMBNAIL_TASK_TYPE.to_string()
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn max_retries(&self) -> i32 {
+        3
+    }
+
+    fn backoff(&self, attempt: u32) -> u32 {
+        60 * u32::pow(2, attempt)
+    }
+}
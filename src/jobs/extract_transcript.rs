@@ -1,4 +1,7 @@
+use crate::entities::prelude::InstagramVideo;
 use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
 use anyhow::bail;
 use async_trait::async_trait;
 use fang::{AsyncQueueable, AsyncRunnable, Deserialize, FangError, Serialize};
@@ -15,22 +18,34 @@ use tokio::fs::File;
 use tokio::process::Command;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use tracing::error;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq, ToSchema)]
 pub struct Transcript {
     pub text: String,
     pub segments: Vec<Segment>,
 
+    /// Language whisper detected in the audio, or was told it's in. The text is in this
+    /// language unless `translated`.
+    #[serde(default)]
+    pub language: Option<String>,
+
+    /// Whether whisper translated the text into English.
+    #[serde(default)]
+    pub translated: bool,
+
     #[serde(flatten)]
     pub other: HashMap<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq, ToSchema)]
 pub struct Segment {
-    id: i32,
-    start: OrderedFloat<f64>,
-    end: OrderedFloat<f64>,
-    text: String,
+    pub id: i32,
+    #[schema(value_type = f64)]
+    pub start: OrderedFloat<f64>,
+    #[schema(value_type = f64)]
+    pub end: OrderedFloat<f64>,
+    pub text: String,
 
     #[serde(flatten)]
     other: HashMap<String, Value>,
@@ -45,7 +60,7 @@ impl ExtractTranscript {
         video_path: &Path,
     ) -> anyhow::Result<Transcript> {
         tracing::info!("Extracting transcript");
-        let audio_path = Self::extract_audio(&video_path).await?;
+        let audio_path = Self::extract_audio(video_path).await?;
         let audio_file = File::open(audio_path).await?;
         let audio = FramedRead::new(audio_file, BytesCodec::new());
         let audio = Body::wrap_stream(audio);
@@ -53,25 +68,44 @@ impl ExtractTranscript {
             .file_name("audio.wav")
             .mime_str("audio/wav")?;
 
-        let raw_output = Client::new()
-            .post(&context.whisper_url)
-            .bearer_auth(&context.whisper_key)
-            .multipart(
-                multipart::Form::new()
-                    .text("model", "whisper-1")
-                    .text("response_format", "verbose_json")
-                    .text("language", "en")
-                    .part("file", audio),
-            )
-            .send()
-            .await?
-            .text()
-            .await?;
+        let mut form = multipart::Form::new()
+            .text("model", "whisper-1")
+            .text("response_format", "verbose_json");
+
+        if let Some(language) = &context.whisper_language {
+            form = form.text("language", language.clone());
+        }
 
-        let output = serde_json::from_str::<_>(&raw_output);
+        if context.whisper_translate {
+            form = form.text("translate", "true");
+        }
+
+        let (status, raw_output) = telemetry::time_stage("transcribe", async {
+            let response = Client::new()
+                .post(&context.whisper_url)
+                .bearer_auth(&context.whisper_key)
+                .multipart(form.part("file", audio))
+                .send()
+                .await?;
+
+            let status = response.status();
+            response.text().await.map(|text| (status, text))
+        })
+        .await?;
+
+        if !status.is_success() {
+            error!("Returned output: {:?}", raw_output);
+            bail!("Whisper failed with {status}: {raw_output}")
+        }
+
+        let output = serde_json::from_str::<Transcript>(&raw_output);
 
         match output {
-            Ok(output) => Ok(output),
+            Ok(mut output) => {
+                output.translated = context.whisper_translate;
+                tracing::info!("Transcribed, language = {:?}", output.language);
+                Ok(output)
+            }
             Err(ref err) => {
                 let unstructured_json = serde_json::from_str::<Value>(&raw_output);
 
@@ -97,13 +131,16 @@ impl ExtractTranscript {
             return Ok(audio_path);
         }
 
-        let output = Command::new("ffmpeg")
-            .arg("-i")
-            .arg(video_path)
-            .args(&["-vn", "-ar", "16000", "-ac", "2", "-ab", "320k"])
-            .arg(&audio_path)
-            .output()
-            .await?;
+        let output = telemetry::time_stage(
+            "extract_audio",
+            Command::new("ffmpeg")
+                .arg("-i")
+                .arg(video_path)
+                .args(["-vn", "-ar", "16000", "-ac", "2", "-ab", "320k"])
+                .arg(&audio_path)
+                .output(),
+        )
+        .await?;
 
         if !output.status.success() {
             bail!(
@@ -116,16 +153,31 @@ impl ExtractTranscript {
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub struct RecipeDestination {
+    pub owner_id: i32,
+    pub household_id: i32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "fang::serde")]
 pub struct ExtractTranscriptJob {
     pub video_id: i32,
     pub reel_id: String,
+
+    /// Extract recipes into this household's library once transcribed and the on-screen text
+    /// read, as `FetchReelJob` does.
+    #[serde(default)]
+    pub extract_recipes_for: Option<RecipeDestination>,
+
+    #[serde(default)]
+    pub trace_context: TraceContext,
 }
 
 impl ExtractTranscriptJob {
     pub async fn new(video_id: i32, db: &DatabaseConnection) -> anyhow::Result<Self> {
-        let (reel_id, ) = crate::entities::instagram_video::Entity::find()
+        let (reel_id, ) = InstagramVideo::find()
             .select_only()
             .columns([crate::entities::instagram_video::Column::InstagramId])
             .filter(crate::entities::instagram_video::Column::Id.eq(video_id))
@@ -134,7 +186,22 @@ impl ExtractTranscriptJob {
             .await?
             .ok_or(anyhow::anyhow!("Video not found"))?;
 
-        Ok(Self { video_id, reel_id })
+        Ok(Self {
+            video_id,
+            reel_id,
+            extract_recipes_for: None,
+            trace_context: TraceContext::current(),
+        })
+    }
+
+    /// Queue LLM extraction into `household_id`'s library after transcribing.
+    pub fn then_extract_recipes(mut self, owner_id: i32, household_id: i32) -> Self {
+        self.extract_recipes_for = Some(RecipeDestination {
+            owner_id,
+            household_id,
+        });
+
+        self
     }
 
     async fn exec(&self, context: &JobContext) -> anyhow::Result<()> {
@@ -158,19 +225,40 @@ impl ExtractTranscriptJob {
 #[typetag::serde]
 #[async_trait]
 impl AsyncRunnable for ExtractTranscriptJob {
-    #[tracing::instrument(skip(_queue))]
-    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    #[tracing::instrument(skip(queue))]
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
         let context = JOB_CONTEXT.get().ok_or(FangError {
             description: "Failed to read context".to_string(),
         })?;
 
-        self.exec(context).await.map_err(|e| FangError {
-            description: e.to_string(),
+        self.exec(context).await.map_err(|e| {
+            telemetry::record_job_outcome(crate::jobs::EXTRACT_TRANSCRIPT_TASK_TYPE, "error");
+            FangError {
+                description: e.to_string(),
+            }
         })?;
 
+        telemetry::record_job_outcome(crate::jobs::EXTRACT_TRANSCRIPT_TASK_TYPE, "success");
+
+        // Reading the on-screen text queues the recipe extraction once it's done
+        if let Some(destination) = self.extract_recipes_for {
+            let job = crate::jobs::extract_on_screen_text::ExtractOnScreenTextJob::new(
+                self.video_id,
+                self.reel_id.clone(),
+                Some(destination),
+            );
+            crate::jobs::enqueue(queue, &context.db, &job).await?;
+        }
+
         Ok(())
     }
 
+    fn task_type(&self) -> String {
+        crate::jobs::EXTRACT_TRANSCRIPT_TASK_TYPE.to_string()
+    }
+
     fn uniq(&self) -> bool {
         true
     }
@@ -0,0 +1,369 @@
+//! Image posts (`/p/` links), often a carousel with the recipe card photographed in one of the
+//! slides. yt-dlp only handles video, so the images are downloaded with gallery-dl, kept for the
+//! recipe page, and read with OCR in place of a transcript.
+
+use crate::entities::instagram_video::Model;
+use crate::entities::{instagram_video, recipes};
+use crate::jobs::extract_on_screen_text::OnScreenText;
+use crate::jobs::extract_thumbnail::ExtractThumbnailJob;
+use crate::jobs::fetch_reel::{FetchReelJob, ReelInfo};
+use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use fang::{AsyncQueueable, AsyncRunnable, Deserialize, FangError, Serialize};
+use lazy_static::lazy_static;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter, Set};
+use serde_json::Value;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+lazy_static! {
+    static ref POST_REGEX: regex::Regex =
+        regex::Regex::new(r"https://www.instagram.com/p/([a-zA-Z0-9_-]+)(?:/.*)?")
+            .expect("Failed to compile regex");
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "fang::serde")]
+pub(crate) struct FetchPostJob {
+    pub(crate) post_url: String,
+    pub(crate) post_id: String,
+    pub(crate) auto_llm: bool,
+
+    /// User who submitted the post, recipes extracted from it land in their library.
+    pub(crate) owner_id: i32,
+
+    /// Household whose shared library the recipes are added to.
+    pub(crate) household_id: i32,
+
+    #[serde(default)]
+    pub(crate) trace_context: TraceContext,
+}
+
+impl FetchPostJob {
+    pub fn new(
+        post_url: String,
+        auto_llm: bool,
+        owner_id: i32,
+        household_id: i32,
+    ) -> anyhow::Result<Self> {
+        let post_id = POST_REGEX
+            .captures(&post_url)
+            .and_then(|captures| captures.get(1))
+            .ok_or(anyhow!("Invalid URL"))?
+            .as_str()
+            .to_string();
+
+        Ok(Self {
+            post_url,
+            post_id,
+            auto_llm,
+            owner_id,
+            household_id,
+            trace_context: TraceContext::current(),
+        })
+    }
+
+    pub async fn exec(&self, context: &JobContext) -> anyhow::Result<Option<Model>> {
+        tracing::info!("Fetching post");
+
+        let existing = instagram_video::Entity::find()
+            .filter(instagram_video::Column::InstagramId.eq(&self.post_id))
+            .one(&context.db)
+            .await?;
+
+        // Posts are shared between users like reels, only the recipes are per household
+        if let Some(post) = existing {
+            let owned_recipes = recipes::Entity::find()
+                .filter(recipes::Column::InstagramVideoId.eq(post.id))
+                .filter(recipes::Column::HouseholdId.eq(self.household_id))
+                .count(&context.db)
+                .await?;
+
+            if owned_recipes > 0 {
+                tracing::info!("Post already in the system... skipping");
+                return Ok(None);
+            }
+
+            tracing::info!("Post already downloaded, reusing video id: {}", post.id);
+            return Ok(Some(post));
+        }
+
+        let (info, images) = self.download_post(context).await?;
+
+        let post_dir = context.post_dir(&self.post_id);
+        let paths: Vec<PathBuf> = images.iter().map(|name| post_dir.join(name)).collect();
+
+        let on_screen_text = OnScreenText::read_images(&paths).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to read text from the post's images: {e:?}");
+            None
+        });
+
+        tracing::info!("Adding to instagram_video");
+
+        let post = instagram_video::ActiveModel {
+            instagram_id: Set(self.post_id.clone()),
+            video_url: Set(self.post_url.clone()),
+            info: Set(info),
+            owner_id: Set(Some(self.owner_id)),
+            on_screen_text: Set(on_screen_text),
+            images: Set(Some(images)),
+
+            ..Default::default()
+        }
+        .insert(&context.db)
+        .await?;
+
+        tracing::info!("Added as video id: {}", post.id);
+
+        Ok(Some(post))
+    }
+
+    /// Download the post's images into its folder, returning its info and the images' file
+    /// names in carousel order.
+    async fn download_post(&self, context: &JobContext) -> anyhow::Result<(ReelInfo, Vec<String>)> {
+        tracing::info!("Downloading post");
+
+        let temp_dir = TempDir::new()?;
+
+        let output = telemetry::time_stage(
+            "download",
+            Command::new(&context.gallery_dl_command_string)
+                .args(["--write-info-json", "-D"])
+                .arg(temp_dir.path())
+                // Numbered by position in the carousel, so the names sort in order
+                .args(["-f", "{num:>02}.{extension}", &self.post_url])
+                .output(),
+        )
+        .await?;
+
+        tracing::info!("Post downloaded, status_code = {:?}", output.status);
+
+        if !output.status.success() {
+            bail!(
+                "gallery-dl failed {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        let info: Value =
+            serde_json::from_reader(std::fs::File::open(temp_dir.path().join("info.json"))?)?;
+
+        let paths = std::fs::read_dir(temp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let images = carousel_images(paths);
+
+        if images.is_empty() {
+            bail!("No images in the post");
+        }
+
+        let post_dir = context.post_dir(&self.post_id);
+        std::fs::create_dir_all(&post_dir)?;
+
+        for name in &images {
+            std::fs::rename(temp_dir.path().join(name), post_dir.join(name))?;
+        }
+
+        let description = info
+            .get("description")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        Ok((ReelInfo::from_description(description), images))
+    }
+}
+
+/// File names of the images among gallery-dl's downloads, in carousel order. Carousels can mix
+/// in videos, only the images are kept.
+fn carousel_images(paths: impl IntoIterator<Item = PathBuf>) -> Vec<String> {
+    let mut images: Vec<String> = paths
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        })
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .collect();
+
+    images.sort();
+    images
+}
+
+#[typetag::serde]
+#[async_trait]
+impl AsyncRunnable for FetchPostJob {
+    #[tracing::instrument(skip(queue))]
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
+        let context = JOB_CONTEXT.get().ok_or(FangError {
+            description: "Failed to read context".to_string(),
+        })?;
+
+        let post = self.exec(context).await.map_err(|e| {
+            tracing::error!("{e:?}");
+            telemetry::record_job_outcome(crate::jobs::FETCH_POST_JOB, "error");
+            FangError {
+                description: e.to_string(),
+            }
+        })?;
+
+        telemetry::record_job_outcome(crate::jobs::FETCH_POST_JOB, "success");
+
+        if let Some(post) = post {
+            let job = ExtractThumbnailJob::new(post.id, post.instagram_id.clone());
+            crate::jobs::enqueue(queue, &context.db, &job).await?;
+
+            if self.auto_llm {
+                let job = crate::jobs::llm_extract_details::LLmExtractDetailsJob::new(
+                    post.id,
+                    Some(self.owner_id),
+                    Some(self.household_id),
+                );
+                crate::jobs::enqueue(queue, &context.db, &job).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shares the fetch workers and import spacing with reels, both download from Instagram.
+    fn task_type(&self) -> String {
+        crate::jobs::FETCH_REEL_TASK_TYPE.to_string()
+    }
+
+    fn uniq(&self) -> bool {
+        true
+    }
+
+    fn max_retries(&self) -> i32 {
+        3
+    }
+
+    fn backoff(&self, attempt: u32) -> u32 {
+        60 * u32::pow(2, attempt)
+    }
+}
+
+/// Fetching whichever kind of Instagram link was given.
+pub(crate) enum FetchJob {
+    Reel(FetchReelJob),
+    Post(FetchPostJob),
+}
+
+impl FetchJob {
+    pub fn new(
+        url: String,
+        auto_llm: bool,
+        owner_id: i32,
+        household_id: i32,
+    ) -> anyhow::Result<Self> {
+        if POST_REGEX.is_match(&url) {
+            FetchPostJob::new(url, auto_llm, owner_id, household_id).map(FetchJob::Post)
+        } else {
+            FetchReelJob::new(url, auto_llm, owner_id, household_id).map(FetchJob::Reel)
+        }
+    }
+
+    /// The reel or post id, what the video is stored under.
+    pub fn instagram_id(&self) -> &str {
+        match self {
+            FetchJob::Reel(job) => &job.reel_id,
+            FetchJob::Post(job) => &job.post_id,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        match self {
+            FetchJob::Reel(job) => &job.reel_url,
+            FetchJob::Post(job) => &job.post_url,
+        }
+    }
+
+    pub fn job(&self) -> &dyn AsyncRunnable {
+        match self {
+            FetchJob::Reel(job) => job,
+            FetchJob::Post(job) => job,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post_id(url: &str) -> Option<&str> {
+        POST_REGEX
+            .captures(url)
+            .and_then(|captures| captures.get(1))
+            .map(|id| id.as_str())
+    }
+
+    #[test]
+    fn matches_post_links() {
+        assert_eq!(
+            post_id("https://www.instagram.com/p/C4x-Y_z9/"),
+            Some("C4x-Y_z9")
+        );
+        assert_eq!(
+            post_id("https://www.instagram.com/p/C4xYz9/?img_index=2"),
+            Some("C4xYz9")
+        );
+        assert_eq!(
+            post_id("https://www.instagram.com/p/C4xYz9"),
+            Some("C4xYz9")
+        );
+
+        assert_eq!(post_id("https://www.instagram.com/reel/C4xYz9/"), None);
+        assert_eq!(post_id("https://www.instagram.com/p/"), None);
+    }
+
+    #[test]
+    fn dispatches_posts_and_reels() {
+        let job =
+            FetchJob::new("https://www.instagram.com/p/POST/".to_string(), true, 1, 2).unwrap();
+        assert!(matches!(job, FetchJob::Post(_)));
+        assert_eq!(job.instagram_id(), "POST");
+        assert_eq!(job.url(), "https://www.instagram.com/p/POST/");
+
+        let job = FetchJob::new(
+            "https://www.instagram.com/reel/REEL/".to_string(),
+            false,
+            1,
+            2,
+        )
+        .unwrap();
+        assert!(matches!(job, FetchJob::Reel(_)));
+        assert_eq!(job.instagram_id(), "REEL");
+
+        assert!(FetchJob::new("https://example.com/p/POST/".to_string(), false, 1, 2).is_err());
+    }
+
+    #[test]
+    fn keeps_only_images_in_carousel_order() {
+        let paths = [
+            "10.jpg",
+            "info.json",
+            "02.PNG",
+            "03.mp4",
+            "01.webp",
+            "04.jpeg",
+            "05",
+        ]
+        .map(PathBuf::from);
+
+        assert_eq!(
+            carousel_images(paths),
+            ["01.webp", "02.PNG", "04.jpeg", "10.jpg"]
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use crate::entities::instagram_video;
 use crate::entities::instagram_video::Model;
-use crate::jobs::extract_transcript::ExtractTranscript;
+use crate::entities::{instagram_video, recipes};
+use crate::jobs::extract_on_screen_text::ExtractOnScreenTextJob;
+use crate::jobs::extract_thumbnail::ExtractThumbnailJob;
+use crate::jobs::extract_transcript::{ExtractTranscript, RecipeDestination};
 use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
 use anyhow::{anyhow, bail};
 use fang::async_trait;
 use fang::asynk::async_queue::AsyncQueueable;
@@ -9,7 +13,7 @@ use fang::serde::{Deserialize, Serialize};
 use fang::{AsyncRunnable, FangError};
 use lazy_static::lazy_static;
 use sea_orm::ActiveValue::Set;
-use sea_orm::{ActiveModelTrait, EntityTrait, QueryFilter, QuerySelect};
+use sea_orm::{ActiveModelTrait, EntityTrait, PaginatorTrait, QueryFilter};
 use sea_orm::{ColumnTrait, FromJsonQueryResult};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -18,6 +22,7 @@ use std::fs::File;
 use std::path::PathBuf;
 use tempfile::TempDir;
 use tokio::process::Command;
+use utoipa::ToSchema;
 
 lazy_static! {
     static ref REEL_REGEX: regex::Regex =
@@ -31,9 +36,20 @@ pub(crate) struct FetchReelJob {
     pub(crate) reel_url: String,
     pub(crate) reel_id: String,
     pub(crate) auto_llm: bool,
+
+    /// User who submitted the reel, recipes extracted from it land in their library.
+    #[serde(default)]
+    pub(crate) owner_id: Option<i32>,
+
+    /// Household whose shared library the recipes are added to.
+    #[serde(default)]
+    pub(crate) household_id: Option<i32>,
+
+    #[serde(default)]
+    pub(crate) trace_context: TraceContext,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromJsonQueryResult, Eq, PartialEq, ToSchema)]
 pub struct ReelInfo {
     pub description: String,
 
@@ -41,8 +57,23 @@ pub struct ReelInfo {
     rest: HashMap<String, Value>,
 }
 
+impl ReelInfo {
+    /// Info for a video that didn't come from Instagram, just the description we were given.
+    pub fn from_description(description: String) -> Self {
+        Self {
+            description,
+            rest: HashMap::new(),
+        }
+    }
+}
+
 impl FetchReelJob {
-    pub fn new(reel_url: String, auto_llm: bool) -> anyhow::Result<Self> {
+    pub fn new(
+        reel_url: String,
+        auto_llm: bool,
+        owner_id: i32,
+        household_id: i32,
+    ) -> anyhow::Result<Self> {
         let captures = REEL_REGEX
             .captures(&reel_url)
             .ok_or(anyhow!("Invalid URL"))?;
@@ -53,28 +84,49 @@ impl FetchReelJob {
             .as_str()
             .to_string();
 
-        Ok(Self { reel_url, reel_id, auto_llm })
+        Ok(Self {
+            reel_url,
+            reel_id,
+            auto_llm,
+            owner_id: Some(owner_id),
+            household_id: Some(household_id),
+            trace_context: TraceContext::current(),
+        })
     }
 
-    pub async fn exec(&self, context: &JobContext) -> anyhow::Result<Option<Model>> {
+    /// The video to extract recipes from, and whether it was just downloaded. `None` if the
+    /// household already has its recipes.
+    pub async fn exec(&self, context: &JobContext) -> anyhow::Result<Option<(Model, bool)>> {
         tracing::info!("Fetching reel");
 
         let existing = crate::entities::instagram_video::Entity::find()
             .filter(instagram_video::Column::InstagramId.eq(&self.reel_id))
-            .select_only()
-            .column(instagram_video::Column::Id)
-            .into_tuple::<(i32, )>()
             .one(&context.db)
             .await?;
 
-        if existing.is_some() {
-            tracing::info!("Video already in the system... skipping");
-            return Ok(None);
+        // Videos are shared between users, only the recipes extracted from them are per user
+        if let Some(video) = existing {
+            let owned_recipes = recipes::Entity::find()
+                .filter(recipes::Column::InstagramVideoId.eq(video.id))
+                .filter(match self.household_id {
+                    Some(household_id) => recipes::Column::HouseholdId.eq(household_id),
+                    None => recipes::Column::HouseholdId.is_null(),
+                })
+                .count(&context.db)
+                .await?;
+
+            if owned_recipes > 0 {
+                tracing::info!("Video already in the system... skipping");
+                return Ok(None);
+            }
+
+            tracing::info!("Video already downloaded, reusing video id: {}", video.id);
+            return Ok(Some((video, false)));
         }
 
         let (info, video_path) = self.download_reel(&context).await?;
 
-        let transcript = ExtractTranscript::extract_transcript(&context, &video_path).await?;
+        let transcript = ExtractTranscript::extract_transcript(context, &video_path).await?;
 
         tracing::info!("Adding to instagram_video");
 
@@ -83,6 +135,7 @@ impl FetchReelJob {
             video_url: Set(self.reel_url.clone()),
             info: Set(info),
             transcript: Set(Some(transcript)),
+            owner_id: Set(self.owner_id),
 
             ..Default::default()
         }
@@ -91,7 +144,7 @@ impl FetchReelJob {
 
         tracing::info!("Added as video id: {}", video.id);
 
-        Ok(Some(video))
+        Ok(Some((video, true)))
     }
 
     async fn download_reel(&self, context: &&JobContext) -> anyhow::Result<(ReelInfo, PathBuf)> {
@@ -108,11 +161,20 @@ impl FetchReelJob {
 
         let temp_dir = TempDir::new()?;
 
-        let yt_dlp_output = Command::new(&context.yt_dlp_command_string)
-            .current_dir(&temp_dir.path())
-            .args(&["--write-info-json", "-o", "reel.%(ext)s", &self.reel_url])
-            .output()
-            .await?;
+        let yt_dlp_output = telemetry::time_stage(
+            "download",
+            Command::new(&context.yt_dlp_command_string)
+                .current_dir(temp_dir.path())
+                .args([
+                    "--write-info-json",
+                    "--write-thumbnail",
+                    "--convert-thumbnails",
+                    "jpg",
+                ])
+                .args(["-o", "reel.%(ext)s", &self.reel_url])
+                .output(),
+        )
+        .await?;
 
         tracing::info!("Reel downloaded, status_code = {:?}", yt_dlp_output.status);
 
@@ -130,6 +192,12 @@ impl FetchReelJob {
         std::fs::rename(temp_video_path, &video_path)?;
         std::fs::rename(temp_json, &json_path)?;
 
+        // Not every reel has one, `ExtractThumbnailJob` takes a frame from the video instead
+        let temp_thumbnail = temp_dir.path().join("reel.jpg");
+        if temp_thumbnail.exists() {
+            std::fs::rename(temp_thumbnail, context.thumbnail_path(&self.reel_id))?;
+        }
+
         let info: ReelInfo = serde_json::from_reader(File::open(&json_path)?)?;
         Ok((info, video_path))
     }
@@ -138,31 +206,63 @@ impl FetchReelJob {
 #[typetag::serde]
 #[async_trait]
 impl AsyncRunnable for FetchReelJob {
-    #[tracing::instrument(skip(_queue))]
-    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    #[tracing::instrument(skip(queue))]
+    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
         let context = JOB_CONTEXT.get().ok_or(FangError {
             description: "Failed to read context".to_string(),
         })?;
 
         let video = self.exec(context).await.map_err(|e| {
             tracing::error!("{e:?}");
+            telemetry::record_job_outcome(crate::jobs::FETCH_REEL_TASK_TYPE, "error");
             FangError {
                 description: e.to_string(),
             }
         })?;
 
-        if let Some(video) = video {
-            if self.auto_llm {
-                let job = crate::jobs::llm_extract_details::LLmExtractDetailsJob {
-                    video_id: video.id,
-                };
-                _queue.insert_task(&job).await?;
+        telemetry::record_job_outcome(crate::jobs::FETCH_REEL_TASK_TYPE, "success");
+
+        if let Some((video, downloaded)) = video {
+            let job = ExtractThumbnailJob::new(video.id, video.instagram_id.clone());
+            crate::jobs::enqueue(queue, &context.db, &job).await?;
+
+            let destination = self
+                .owner_id
+                .zip(self.household_id)
+                .filter(|_| self.auto_llm)
+                .map(|(owner_id, household_id)| RecipeDestination {
+                    owner_id,
+                    household_id,
+                });
+
+            // Reading the on-screen text queues the recipe extraction once it's done
+            if downloaded {
+                let job =
+                    ExtractOnScreenTextJob::new(video.id, video.instagram_id.clone(), destination);
+                crate::jobs::enqueue(queue, &context.db, &job).await?;
+            }
+
+            // Reused videos have their text read already, and jobs queued before households
+            // have nowhere for the reading to send the recipes
+            if self.auto_llm && (!downloaded || destination.is_none()) {
+                let job = crate::jobs::llm_extract_details::LLmExtractDetailsJob::new(
+                    video.id,
+                    self.owner_id,
+                    self.household_id,
+                );
+                crate::jobs::enqueue(queue, &context.db, &job).await?;
             }
         }
 
         Ok(())
     }
 
+    fn task_type(&self) -> String {
+        crate::jobs::FETCH_REEL_TASK_TYPE.to_string()
+    }
+
     fn uniq(&self) -> bool {
         true
     }
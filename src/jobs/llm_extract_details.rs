@@ -1,6 +1,9 @@
 use crate::entities::instagram_video::Model;
-use crate::entities::{instagram_video, recipes};
-use crate::jobs::{JobContext, JOB_CONTEXT};
+use crate::entities::prelude::LlmExtractionFailures;
+use crate::entities::{instagram_video, llm_extraction_failures, recipes};
+use crate::jobs::{JobContext, JOB_CONTEXT, LLM_EXTRACT_DETAILS_TASK_TYPE};
+use crate::telemetry;
+use crate::telemetry::TraceContext;
 use anyhow::anyhow;
 use async_openai::types::CreateChatCompletionResponse;
 use clap::ValueEnum;
@@ -8,18 +11,116 @@ use fang::async_trait;
 use fang::asynk::async_queue::AsyncQueueable;
 use fang::serde::{Deserialize, Serialize};
 use fang::{AsyncRunnable, FangError};
-use reqwest::Response;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Response, StatusCode};
+use sea_orm::sea_query::OnConflict;
 use sea_orm::ColumnTrait;
 use sea_orm::QueryFilter;
-use sea_orm::{DatabaseConnection, EntityTrait, Set};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Set};
 use serde::de::DeserializeOwned;
 use serde_json::{json, Value};
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "fang::serde")]
 pub(crate) struct LLmExtractDetailsJob {
-    pub video_id: i32,
+    /// Video whose description and transcript the recipes are extracted from.
+    #[serde(default)]
+    pub video_id: Option<i32>,
+
+    /// Text to extract recipes from instead of a video, e.g. one pasted from an email.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    /// User the extracted recipes are attributed to.
+    #[serde(default)]
+    pub owner_id: Option<i32>,
+
+    #[serde(default)]
+    pub household_id: Option<i32>,
+
+    #[serde(default)]
+    pub trace_context: TraceContext,
+
+    /// Outcome of the last run, read back by fang through `max_retries` and `backoff`.
+    #[serde(skip)]
+    retry: RetryHint,
+}
+
+#[derive(Debug, Default)]
+struct RetryHint {
+    permanent: AtomicBool,
+    retry_after: AtomicU32,
+}
+
+/// Failure of an extraction, classified by whether trying again could help.
+#[derive(thiserror::Error, Debug)]
+pub enum LlmError {
+    /// Timeouts, connection failures, 429 and 5xx responses.
+    #[error("{error}")]
+    Transient {
+        error: anyhow::Error,
+        retry_after: Option<Duration>,
+    },
+
+    /// Malformed model output, 4xx responses and missing videos.
+    #[error("{0}")]
+    Permanent(anyhow::Error),
+}
+
+impl From<anyhow::Error> for LlmError {
+    fn from(error: anyhow::Error) -> Self {
+        LlmError::Transient {
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+impl From<DbErr> for LlmError {
+    fn from(err: DbErr) -> Self {
+        anyhow::Error::from(err).into()
+    }
+}
+
+impl From<serde_json::Error> for LlmError {
+    fn from(err: serde_json::Error) -> Self {
+        LlmError::Permanent(err.into())
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) if !is_transient_status(status) => LlmError::Permanent(err.into()),
+            _ => LlmError::Transient {
+                error: err.into(),
+                retry_after: None,
+            },
+        }
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// Parses a `Retry-After` header given either as delay seconds or as an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,6 +128,30 @@ struct ExtractedRecipe {
     title: String,
     ingredients: Vec<String>,
     instructions: Vec<String>,
+
+    #[serde(default)]
+    servings: Option<String>,
+
+    #[serde(default, deserialize_with = "lenient_minutes")]
+    prep_minutes: Option<i32>,
+
+    #[serde(default, deserialize_with = "lenient_minutes")]
+    cook_minutes: Option<i32>,
+}
+
+/// Minutes as a number, or a duration as models sometimes write `"15 minutes"` or `"1 hour"`.
+/// Anything else is treated as not given rather than failing the whole recipe.
+fn lenient_minutes<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let minutes = match Value::deserialize(deserializer)? {
+        Value::Number(number) => number.as_f64().map(|minutes| minutes.round() as i32),
+        Value::String(text) => crate::interop::parse_minutes(&text),
+        _ => None,
+    };
+
+    Ok(minutes.filter(|minutes| *minutes > 0))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -61,39 +186,164 @@ impl AcceptableResponses {
     }
 }
 
+/// What the recipes are extracted from, as the prompt template sees it.
+#[derive(Serialize)]
+#[serde(crate = "fang::serde")]
+struct PromptSources<'a> {
+    description: &'a str,
+    transcript: &'a str,
+    /// Text burned into the video's frames, often the only place quantities are given.
+    on_screen_text: &'a str,
+    /// Language spoken in the video as whisper detected it, empty if unknown.
+    source_language: &'a str,
+    /// Language to write the recipes in.
+    output_language: &'a str,
+}
+
 impl LLmExtractDetailsJob {
-    async fn exec(&self, context: &JobContext) -> anyhow::Result<()> {
+    pub fn new(video_id: i32, owner_id: Option<i32>, household_id: Option<i32>) -> Self {
+        Self {
+            video_id: Some(video_id),
+            text: None,
+            owner_id,
+            household_id,
+            trace_context: TraceContext::current(),
+            retry: RetryHint::default(),
+        }
+    }
+
+    /// Extract recipes from free text rather than a video, through the same prompt.
+    pub fn from_text(text: String, owner_id: i32, household_id: i32) -> Self {
+        Self {
+            video_id: None,
+            text: Some(text),
+            owner_id: Some(owner_id),
+            household_id: Some(household_id),
+            trace_context: TraceContext::current(),
+            retry: RetryHint::default(),
+        }
+    }
+
+    async fn exec(&self, context: &JobContext) -> Result<(), LlmError> {
         tracing::info!("Using LLM to extract details from recipe description");
 
-        let video: Model = instagram_video::Entity::find()
-            .filter(instagram_video::Column::Id.eq(self.video_id))
-            .one(&context.db)
-            .await?
-            .ok_or(anyhow!("Video not found"))?;
+        let video: Option<Model> = match self.video_id {
+            Some(video_id) => Some(
+                instagram_video::Entity::find()
+                    .filter(instagram_video::Column::Id.eq(video_id))
+                    .one(&context.db)
+                    .await?
+                    .ok_or(LlmError::Permanent(anyhow!("Video not found")))?,
+            ),
+
+            None => None,
+        };
 
-        let recipes_in_description = self.extract_recipes(context, &video).await?;
+        // Pasted text stands in for a reel's description, there's nothing to transcribe
+        let sources = match (&video, &self.text) {
+            (Some(video), _) => PromptSources {
+                description: video.info.description.as_str(),
+                transcript: video.transcript.as_ref().map_or("", |t| t.text.as_str()),
+                on_screen_text: video.on_screen_text.as_deref().unwrap_or(""),
+                source_language: video
+                    .transcript
+                    .as_ref()
+                    .and_then(|t| t.language.as_deref())
+                    .unwrap_or(""),
+                output_language: &context.recipe_language,
+            },
+            (None, Some(text)) => PromptSources {
+                description: text.as_str(),
+                transcript: "",
+                on_screen_text: "",
+                source_language: "",
+                output_language: &context.recipe_language,
+            },
+            (None, None) => {
+                return Err(LlmError::Permanent(anyhow!(
+                    "Nothing to extract recipes from"
+                )))
+            }
+        };
+
+        let recipes_in_description = self.extract_recipes(context, &sources).await?;
         tracing::info!(
             "Found {} recipes in description",
             recipes_in_description.len()
         );
 
-        self.save_newly_recipes(&context.db, &recipes_in_description, &video)
+        if video.is_none() && recipes_in_description.is_empty() {
+            return Err(LlmError::Permanent(anyhow!("No recipes found in the text")));
+        }
+
+        self.save_newly_recipes(&context.db, &recipes_in_description, video.as_ref())
             .await?;
         tracing::info!("Added completed recipe to database");
 
+        if let (Some(video), Some(household_id)) = (&video, self.household_id) {
+            LlmExtractionFailures::delete_many()
+                .filter(llm_extraction_failures::Column::InstagramVideoId.eq(video.id))
+                .filter(llm_extraction_failures::Column::HouseholdId.eq(household_id))
+                .exec(&context.db)
+                .await?;
+        }
+
         Ok(())
     }
 
-    async fn handle_response<T: DeserializeOwned + Debug>(response: Response) -> anyhow::Result<T> {
-        if !response.status().is_success() {
+    /// Record a permanent failure so the video shows up on the household's failures page.
+    /// Failures for pasted text, or from jobs queued before households, only show up in the
+    /// job's status.
+    async fn record_failure(&self, db: &DatabaseConnection, reason: &str) -> anyhow::Result<()> {
+        let (Some(video_id), Some(household_id)) = (self.video_id, self.household_id) else {
+            return Ok(());
+        };
+
+        LlmExtractionFailures::insert(llm_extraction_failures::ActiveModel {
+            instagram_video_id: Set(video_id),
+            reason: Set(reason.to_string()),
+            failed_at: Set(chrono::Utc::now().fixed_offset()),
+            household_id: Set(household_id),
+
+            ..Default::default()
+        })
+        .on_conflict(
+            OnConflict::columns([
+                llm_extraction_failures::Column::HouseholdId,
+                llm_extraction_failures::Column::InstagramVideoId,
+            ])
+            .update_columns([
+                llm_extraction_failures::Column::Reason,
+                llm_extraction_failures::Column::FailedAt,
+            ])
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn handle_response<T: DeserializeOwned + Debug>(
+        response: Response,
+    ) -> Result<T, LlmError> {
+        let status = response.status();
+
+        if !status.is_success() {
             tracing::error!("Failed to send request, response metadata: {:#?}", response);
-            let response_status_code = response.status().as_u16();
+            let retry_after = parse_retry_after(response.headers());
             let response_body = response.text().await?;
             tracing::error!(
                 "Failed to send request, response content: {:#?}",
                 response_body
             );
-            return Err(anyhow!("Failed to send request: {}", response_status_code));
+
+            let error = anyhow!("Failed to send request: {}", status.as_u16());
+            return Err(if is_transient_status(status) {
+                LlmError::Transient { error, retry_after }
+            } else {
+                LlmError::Permanent(error)
+            });
         }
 
         let response = response.text().await?;
@@ -105,36 +355,27 @@ impl LLmExtractDetailsJob {
         Ok(response)
     }
 
-    fn fetch_prompt(&self, llm_method: LlmMethod) -> String {
+    fn fetch_prompt(&self, _llm_method: LlmMethod) -> String {
         let dynamic = true;
 
-        let prompt_template = if dynamic {
+        if dynamic {
             std::fs::read_to_string("app/prompts/extract_recipe_details.txt").unwrap()
         } else {
             include_str!("../../app/prompts/extract_recipe_details.txt").to_string()
-        };
-
-        prompt_template
+        }
     }
 
     async fn extract_recipes(
         &self,
         context: &JobContext,
-        instagram_video: &Model,
-    ) -> anyhow::Result<Vec<ExtractedRecipe>> {
+        sources: &PromptSources<'_>,
+    ) -> Result<Vec<ExtractedRecipe>, LlmError> {
         let completion_url = &context.completion_url;
         let api_key = &context.completion_key;
         let llm_model = &context.completion_model;
 
-        let transcript = match &instagram_video.transcript {
-            Some(transcript) => &transcript.text,
-            None => "",
-        };
-
-        let description = instagram_video.info.description.as_str();
-
         let prompt_template = self.fetch_prompt(context.completion_mode);
-        let prompt = Self::assemble_prompt(&prompt_template, description, transcript);
+        let prompt = Self::assemble_prompt(&prompt_template, sources);
         tracing::info!("Prompt prepared");
 
         match context.completion_mode {
@@ -151,26 +392,36 @@ impl LLmExtractDetailsJob {
 
                 tracing::info!("Request prepared: {:#?}", request);
 
-                let response = reqwest::Client::new()
-                    .post(completion_url)
-                    .bearer_auth(api_key)
-                    .json(&request)
-                    .send()
-                    .await?;
+                let response = telemetry::time_stage(
+                    "completion",
+                    reqwest::Client::new()
+                        .post(completion_url)
+                        .bearer_auth(api_key)
+                        .json(&request)
+                        .send(),
+                )
+                .await?;
 
                 let response: CreateChatCompletionResponse =
                     Self::handle_response(response).await?;
 
+                if let Some(usage) = &response.usage {
+                    telemetry::record_llm_tokens(
+                        usage.prompt_tokens.into(),
+                        usage.completion_tokens.into(),
+                    );
+                }
+
                 let response = response.choices[0]
                     .message
                     .content
                     .clone()
-                    .ok_or(anyhow!("No content in response"))?;
+                    .ok_or(LlmError::Permanent(anyhow!("No content in response")))?;
 
                 // If we are not in Llama json mode, we may need to strip a code block
                 let message = response.replace("```json\n", "").replace("```", "");
 
-                return Ok(serde_json::from_str::<AcceptableResponses>(&message)?.retrieve());
+                Ok(serde_json::from_str::<AcceptableResponses>(&message)?.retrieve())
             }
 
             LlmMethod::OllamaJson => {
@@ -183,22 +434,30 @@ impl LLmExtractDetailsJob {
 
                 tracing::info!("Request prepared: {:#?}", request);
 
-                let response = reqwest::Client::new()
-                    .post(completion_url)
-                    .bearer_auth(api_key)
-                    .json(&request)
-                    .send()
-                    .await?;
+                let response = telemetry::time_stage(
+                    "completion",
+                    reqwest::Client::new()
+                        .post(completion_url)
+                        .bearer_auth(api_key)
+                        .json(&request)
+                        .send(),
+                )
+                .await?;
 
                 let response: OllamaGenerateResponse = Self::handle_response(response).await?;
 
+                telemetry::record_llm_tokens(
+                    response.rest["prompt_eval_count"].as_u64().unwrap_or(0),
+                    response.rest["eval_count"].as_u64().unwrap_or(0),
+                );
+
                 // If we are not in Llama json mode, we may need to strip a code block
                 let message = response
                     .response
                     .replace("```json\n", "")
                     .replace("```", "");
 
-                return Ok(serde_json::from_str::<AcceptableResponses>(&message)?.retrieve());
+                Ok(serde_json::from_str::<AcceptableResponses>(&message)?.retrieve())
             }
 
             LlmMethod::OpenAITools => {
@@ -211,37 +470,36 @@ impl LLmExtractDetailsJob {
         }
     }
 
-    fn assemble_prompt(prompt_template: &String, description: &str, transcript: &str) -> String {
+    fn assemble_prompt(prompt_template: &str, sources: &PromptSources<'_>) -> String {
         let env = {
             let mut env = minijinja::Environment::new();
-            env.add_template("prompt", &prompt_template).unwrap();
+            env.add_template("prompt", prompt_template).unwrap();
             env
         };
 
         let template = env.get_template("prompt").unwrap();
 
-        let prompt = template
-            .render(json!({
-                "description": description,
-                "transcript": transcript,
-            }))
-            .unwrap();
-        prompt
+        template.render(sources).unwrap()
     }
 
     async fn save_newly_recipes(
         &self,
         db: &DatabaseConnection,
         recipes_in_description: &[ExtractedRecipe],
-        instagram_video: &Model,
+        instagram_video: Option<&Model>,
     ) -> anyhow::Result<()> {
         recipes::Entity::insert_many(recipes_in_description.iter().map(|recipe| {
             recipes::ActiveModel {
-                instagram_video_id: Set(Some(instagram_video.id.clone())),
+                instagram_video_id: Set(instagram_video.map(|video| video.id)),
                 title: Set(Some(recipe.title.clone())),
                 ingredients: Set(Some(recipe.ingredients.clone())),
                 instructions: Set(Some(recipe.instructions.clone())),
+                servings: Set(recipe.servings.clone()),
+                prep_minutes: Set(recipe.prep_minutes),
+                cook_minutes: Set(recipe.cook_minutes),
                 generated_at: Set(Some(chrono::Utc::now().fixed_offset())),
+                owner_id: Set(self.owner_id),
+                household_id: Set(self.household_id),
 
                 ..Default::default()
             }
@@ -249,6 +507,12 @@ impl LLmExtractDetailsJob {
         .exec(db)
         .await?;
 
+        // Recipes from imported reels join the Instagram collections they were saved to
+        if let (Some(household_id), Some(video)) = (self.household_id, instagram_video) {
+            crate::collections::add_imported_recipes(db, household_id, Some(&video.instagram_id))
+                .await?;
+        }
+
         Ok(())
     }
 }
@@ -258,18 +522,171 @@ impl LLmExtractDetailsJob {
 impl AsyncRunnable for LLmExtractDetailsJob {
     #[tracing::instrument(skip(_queue))]
     async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+        self.trace_context.attach(&tracing::Span::current());
+
         let context = JOB_CONTEXT.get().ok_or(FangError {
             description: "Failed to read context".to_string(),
         })?;
 
-        self.exec(context).await.map_err(|e| FangError {
-            description: e.to_string(),
-        })?;
+        match self.exec(context).await {
+            Ok(()) => {
+                telemetry::record_job_outcome(LLM_EXTRACT_DETAILS_TASK_TYPE, "success");
+                Ok(())
+            }
 
-        Ok(())
+            Err(LlmError::Transient { error, retry_after }) => {
+                tracing::warn!("Transient failure, will retry: {error:?}");
+                telemetry::record_job_outcome(LLM_EXTRACT_DETAILS_TASK_TYPE, "transient_error");
+
+                if let Some(retry_after) = retry_after {
+                    let seconds = u32::try_from(retry_after.as_secs()).unwrap_or(u32::MAX);
+                    self.retry.retry_after.store(seconds, Ordering::Relaxed);
+                }
+
+                Err(FangError {
+                    description: error.to_string(),
+                })
+            }
+
+            Err(LlmError::Permanent(reason)) => {
+                tracing::error!("Permanent failure, giving up: {reason:?}");
+                telemetry::record_job_outcome(LLM_EXTRACT_DETAILS_TASK_TYPE, "permanent_error");
+                self.retry.permanent.store(true, Ordering::Relaxed);
+
+                let description = reason.to_string();
+                if let Err(e) = self.record_failure(&context.db, &description).await {
+                    tracing::error!("Failed to record failure: {e:?}");
+                }
+
+                Err(FangError { description })
+            }
+        }
+    }
+
+    fn task_type(&self) -> String {
+        LLM_EXTRACT_DETAILS_TASK_TYPE.to_string()
     }
 
     fn uniq(&self) -> bool {
         true
     }
+
+    fn max_retries(&self) -> i32 {
+        if self.retry.permanent.load(Ordering::Relaxed) {
+            0
+        } else {
+            3
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> u32 {
+        let exponential = 60 * u32::pow(2, attempt);
+
+        match self.retry.retry_after.load(Ordering::Relaxed) {
+            0 => exponential,
+            retry_after => retry_after,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minutes(value: Value) -> Option<i32> {
+        let recipe: ExtractedRecipe = serde_json::from_value(json!({
+            "title": "Pasta",
+            "ingredients": [],
+            "instructions": [],
+            "prep_minutes": value,
+        }))
+        .unwrap();
+
+        recipe.prep_minutes
+    }
+
+    #[test]
+    fn reads_minutes_leniently() {
+        assert_eq!(minutes(json!(15)), Some(15));
+        assert_eq!(minutes(json!(12.6)), Some(13));
+        assert_eq!(minutes(json!("15 minutes")), Some(15));
+        assert_eq!(minutes(json!("1 hour")), Some(60));
+        assert_eq!(minutes(json!("1 hr 20 mins")), Some(80));
+        assert_eq!(minutes(json!("overnight")), None);
+        assert_eq!(minutes(json!(0)), None);
+        assert_eq!(minutes(json!(null)), None);
+    }
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response {
+        let mut response = axum::http::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            response = response.header(RETRY_AFTER, retry_after);
+        }
+
+        response.body("{}").unwrap().into()
+    }
+
+    fn is_transient(error: &LlmError) -> bool {
+        matches!(error, LlmError::Transient { .. })
+    }
+
+    #[test]
+    fn classifies_http_errors_by_status() {
+        for (status, transient) in [
+            (429, true),
+            (408, true),
+            (503, true),
+            (400, false),
+            (401, false),
+        ] {
+            let error = response(status, None).error_for_status().unwrap_err();
+            assert_eq!(is_transient(&error.into()), transient, "{status}");
+        }
+    }
+
+    #[test]
+    fn errors_without_a_status_are_transient() {
+        let error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        assert!(error.status().is_none());
+        assert!(is_transient(&error.into()));
+    }
+
+    #[tokio::test]
+    async fn failed_responses_keep_their_retry_after() {
+        let error = LLmExtractDetailsJob::handle_response::<Value>(response(429, Some("30")))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            LlmError::Transient { retry_after: Some(delay), .. } if delay == Duration::from_secs(30)
+        ));
+
+        let error = LLmExtractDetailsJob::handle_response::<Value>(response(400, None))
+            .await
+            .unwrap_err();
+        assert!(!is_transient(&error));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds_and_dates() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(
+            parse_retry_after(&headers(" 120 ")),
+            Some(Duration::from_secs(120))
+        );
+
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let delay = parse_retry_after(&headers(&date)).unwrap();
+        assert!(delay > Duration::from_secs(80) && delay <= Duration::from_secs(90));
+
+        let past = (chrono::Utc::now() - chrono::Duration::seconds(90)).to_rfc2822();
+        assert_eq!(parse_retry_after(&headers(&past)), None);
+        assert_eq!(parse_retry_after(&headers("soon")), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+    }
 }
@@ -1,57 +1,195 @@
-use crate::cli::Cli;
+use crate::cli::{Cli, WorkerArgs};
 use crate::jobs::llm_extract_details::LlmMethod;
+use fang::{
+    AsyncQueue, AsyncQueueable, AsyncRunnable, AsyncWorkerPool, FangError, NoTls, RetentionMode,
+    Task,
+};
 use once_cell::sync::OnceCell;
-use sea_orm::DatabaseConnection;
-use sqlx::PgPool;
+use sea_orm::prelude::Uuid;
+use sea_orm::DatabaseBackend::Postgres;
+use sea_orm::{ConnectionTrait, DatabaseConnection, FromQueryResult, Statement};
 use std::ffi::OsString;
 use std::path::PathBuf;
 
+pub mod extract_on_screen_text;
+pub mod extract_thumbnail;
 pub mod extract_transcript;
+pub mod fetch_post;
 pub mod fetch_reel;
 pub mod llm_extract_details;
 
+pub const FETCH_REEL_TASK_TYPE: &str = "fetch_reel";
+pub const EXTRACT_TRANSCRIPT_TASK_TYPE: &str = "extract_transcript";
+pub const LLM_EXTRACT_DETAILS_TASK_TYPE: &str = "llm_extract_details";
+pub const EXTRACT_THUMBNAIL_TASK_TYPE: &str = "extract_thumbnail";
+pub const EXTRACT_ON_SCREEN_TEXT_TASK_TYPE: &str = "extract_on_screen_text";
+
+/// Posts are fetched by the `FETCH_REEL_TASK_TYPE` workers, but counted in metrics on their own.
+pub const FETCH_POST_JOB: &str = "fetch_post";
+
 #[derive(Debug, Clone)]
 pub struct JobContext {
     pub db: DatabaseConnection,
-    pub raw_db: PgPool,
     pub yt_dlp_command_string: OsString,
+    pub gallery_dl_command_string: OsString,
     pub reel_dir: PathBuf,
 
     pub whisper_url: String,
     pub whisper_key: String,
+    /// `None` to leave the language out of the request.
+    pub whisper_language: Option<String>,
+    pub whisper_translate: bool,
 
     pub completion_url: String,
     pub completion_key: String,
     pub completion_model: String,
     pub completion_mode: LlmMethod,
+    pub recipe_language: String,
 }
 
 impl JobContext {
-    pub fn new(db: DatabaseConnection, raw_db: PgPool, cli: &Cli) -> JobContext {
+    pub fn new(db: DatabaseConnection, cli: &Cli) -> JobContext {
         JobContext {
             db,
-            raw_db,
 
             yt_dlp_command_string: cli
                 .yt_dlp_path
                 .as_ref()
                 .map_or_else(|| "yt-dlp".into(), |p| p.into()),
 
+            gallery_dl_command_string: cli
+                .gallery_dl_path
+                .as_ref()
+                .map_or_else(|| "gallery-dl".into(), |p| p.into()),
+
             reel_dir: cli.reel_dir.clone(),
 
             whisper_url: cli.whisper_url.clone(),
             whisper_key: cli.whisper_key.clone(),
+            whisper_language: match &cli.whisper_language {
+                Some(language) => Some(language.clone()).filter(|l| !l.is_empty()),
+                None => cli
+                    .whisper_url
+                    .ends_with("/inference")
+                    .then(|| "auto".to_string()),
+            },
+            whisper_translate: cli.whisper_translate,
 
             completion_url: cli.completion_url.clone(),
             completion_key: cli.completion_key.clone(),
             completion_model: cli.completion_model.clone(),
             completion_mode: cli.completion_mode,
+            recipe_language: cli.recipe_language.clone(),
         }
     }
 
     pub fn video_path(&self, reel_id: &str) -> PathBuf {
-        self.reel_dir.join(&reel_id).with_extension("mp4")
+        self.reel_dir.join(reel_id).with_extension("mp4")
+    }
+
+    pub fn thumbnail_path(&self, reel_id: &str) -> PathBuf {
+        self.reel_dir.join(reel_id).with_extension("jpg")
+    }
+
+    /// Folder holding the images of a carousel post.
+    pub fn post_dir(&self, post_id: &str) -> PathBuf {
+        self.reel_dir.join(post_id)
+    }
+
+    /// Folder holding the photos of recipes imported from other recipe managers.
+    pub fn recipe_image_dir(&self) -> PathBuf {
+        self.reel_dir.join("recipe-images")
     }
 }
 
 pub(crate) static JOB_CONTEXT: OnceCell<JobContext> = OnceCell::new();
+
+fn fang_error(error: impl std::fmt::Display) -> FangError {
+    FangError {
+        description: error.to_string(),
+    }
+}
+
+/// Id of a task the same as `task` that is still waiting to run.
+///
+/// Payloads carry the trace context of whoever queued them, so two requests for the same reel
+/// never have the same payload, and fang's `uniq` hash never matches. This compares them without
+/// it.
+pub async fn find_queued(
+    db: &impl ConnectionTrait,
+    task: &dyn AsyncRunnable,
+) -> Result<Option<Uuid>, FangError> {
+    #[derive(FromQueryResult)]
+    struct Queued {
+        id: Uuid,
+    }
+
+    let mut metadata = serde_json::to_value(task).map_err(fang_error)?;
+    if let Some(fields) = metadata.as_object_mut() {
+        fields.remove("trace_context");
+    }
+
+    let queued = Queued::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"select id from fang_tasks where task_type = $1 and state in ('new', 'retried') and metadata - 'trace_context' = $2 limit 1;"#,
+        vec![task.task_type().into(), metadata.into()],
+    ))
+    .one(db)
+    .await
+    .map_err(fang_error)?;
+
+    Ok(queued.map(|queued| queued.id))
+}
+
+/// Queue `task`, or if it's unique and the same job is already waiting, return that one instead.
+pub async fn enqueue(
+    queue: &mut dyn AsyncQueueable,
+    db: &impl ConnectionTrait,
+    task: &dyn AsyncRunnable,
+) -> Result<Task, FangError> {
+    if task.uniq() {
+        if let Some(id) = find_queued(db, task).await? {
+            return Ok(queue.find_task_by_id(id).await?);
+        }
+    }
+
+    Ok(queue.insert_task(task).await?)
+}
+
+/// Start a worker pool per job type, so slow stages can't starve the others.
+pub async fn start_worker_pools(queue: &AsyncQueue<NoTls>, args: &WorkerArgs) {
+    let pools = [
+        (FETCH_REEL_TASK_TYPE, args.fetch_workers),
+        (EXTRACT_TRANSCRIPT_TASK_TYPE, args.transcribe_workers),
+        (LLM_EXTRACT_DETAILS_TASK_TYPE, args.llm_workers),
+        (EXTRACT_THUMBNAIL_TASK_TYPE, args.thumbnail_workers),
+        (EXTRACT_ON_SCREEN_TEXT_TASK_TYPE, args.ocr_workers),
+    ];
+
+    for (task_type, workers) in pools {
+        let workers = workers.unwrap_or(args.workers);
+
+        if workers == 0 {
+            tracing::info!("No workers for {task_type}, skipping");
+            continue;
+        }
+
+        // Imports show why a download failed, so failed fetches stay in the queue with their error
+        let retention_mode = if task_type == FETCH_REEL_TASK_TYPE {
+            RetentionMode::RemoveFinished
+        } else {
+            RetentionMode::RemoveAll
+        };
+
+        let mut pool: AsyncWorkerPool<AsyncQueue<NoTls>> = AsyncWorkerPool::builder()
+            .number_of_workers(workers)
+            .queue(queue.clone())
+            .task_type(task_type)
+            .retention_mode(retention_mode)
+            .build();
+
+        // This await does nothing, the method is entirely synchronous
+        pool.start().await;
+        tracing::info!("Started {workers} workers for {task_type}");
+    }
+}
@@ -0,0 +1,269 @@
+//! A recipe as a schema.org `Recipe` in JSON-LD, embedded in its page for browser extensions and
+//! served on its own as `/recipes/{id}.jsonld` for other tools.
+//!
+//! The app doesn't know the address it's served on, so links are made absolute with the request's
+//! `Host`, and `X-Forwarded-Proto` when behind a proxy.
+
+use crate::entities::{instagram_video, recipes};
+use crate::error;
+use crate::export;
+use crate::jobs::JobContext;
+use crate::uploads;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE, HOST};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use sea_orm::DatabaseConnection;
+use serde_json::{json, Map, Value};
+
+/// `scheme://host` the request was made to.
+pub fn origin(headers: &HeaderMap) -> String {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = header(HOST.as_str()).unwrap_or("localhost");
+
+    format!("{scheme}://{host}")
+}
+
+/// ISO 8601 duration, e.g. `PT1H5M`.
+fn duration(minutes: i32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("PT{minutes}M"),
+        (hours, 0) => format!("PT{hours}H"),
+        (hours, minutes) => format!("PT{hours}H{minutes}M"),
+    }
+}
+
+/// The schema.org `Recipe` for `recipe`, with the reel it came from as its video, or its images
+/// for a carousel post.
+pub fn recipe(
+    recipe: &recipes::Model,
+    video: Option<&instagram_video::Model>,
+    context: &JobContext,
+    origin: &str,
+) -> Value {
+    let mut json = Map::new();
+    let title = recipe.title.as_deref().unwrap_or("Untitled recipe");
+    let url = format!("{origin}/recipes/{}", recipe.id);
+
+    json.insert("@context".into(), json!("https://schema.org"));
+    json.insert("@type".into(), json!("Recipe"));
+    json.insert("@id".into(), json!(url));
+    json.insert("url".into(), json!(url));
+    json.insert("name".into(), json!(title));
+
+    if let Some(servings) = &recipe.servings {
+        json.insert("recipeYield".into(), json!(servings));
+    }
+    if let Some(minutes) = recipe.prep_minutes {
+        json.insert("prepTime".into(), json!(duration(minutes)));
+    }
+    if let Some(minutes) = recipe.cook_minutes {
+        json.insert("cookTime".into(), json!(duration(minutes)));
+    }
+    if recipe.prep_minutes.is_some() || recipe.cook_minutes.is_some() {
+        let total =
+            recipe.prep_minutes.unwrap_or_default() + recipe.cook_minutes.unwrap_or_default();
+        json.insert("totalTime".into(), json!(duration(total)));
+    }
+
+    json.insert(
+        "recipeIngredient".into(),
+        json!(recipe.ingredients.as_deref().unwrap_or_default()),
+    );
+
+    let steps: Vec<Value> = recipe
+        .instructions
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            json!({
+                "@type": "HowToStep",
+                "position": i + 1,
+                "text": text,
+            })
+        })
+        .collect();
+    json.insert("recipeInstructions".into(), json!(steps));
+
+    if let Some(notes) = &recipe.notes {
+        json.insert("description".into(), json!(notes));
+    }
+
+    let source = recipe
+        .source_url
+        .as_deref()
+        .or(video.and_then(uploads::source_url));
+    if let Some(source) = source {
+        json.insert("isBasedOn".into(), json!(source));
+    }
+
+    let mut images = vec![];
+    if recipe.image.is_some() {
+        images.push(format!("{url}/image"));
+    }
+
+    if let Some(video) = video {
+        let video_url = format!("{origin}/videos/{}", video.instagram_id);
+        let thumbnail = context
+            .thumbnail_path(&video.instagram_id)
+            .exists()
+            .then(|| format!("{video_url}/thumbnail"));
+
+        match video.images.as_deref() {
+            Some(post_images) if !post_images.is_empty() => {
+                images.extend(
+                    post_images
+                        .iter()
+                        .map(|name| format!("{video_url}/images/{name}")),
+                );
+            }
+
+            _ => {
+                let mut video_object = json!({
+                    "@type": "VideoObject",
+                    "name": title,
+                    "description": video.info.description,
+                    "contentUrl": video_url,
+                });
+
+                if let Some(thumbnail) = &thumbnail {
+                    video_object["thumbnailUrl"] = json!(thumbnail);
+                }
+                if let Some(created_at) = video.created_at {
+                    video_object["uploadDate"] = json!(created_at.and_utc().to_rfc3339());
+                }
+
+                json.insert("video".into(), video_object);
+                images.extend(thumbnail);
+            }
+        }
+    }
+
+    if !images.is_empty() {
+        json.insert("image".into(), json!(images));
+    }
+
+    Value::Object(json)
+}
+
+/// The recipe's JSON-LD on its own, served for `/recipes/{id}.jsonld`.
+pub async fn jsonld_recipe(
+    db: &DatabaseConnection,
+    context: &JobContext,
+    headers: &HeaderMap,
+    household_id: i32,
+    recipe_id: i32,
+) -> error::Result<Response> {
+    let (model, video) = export::load_recipe(db, household_id, recipe_id).await?;
+    let json = recipe(&model, video.as_ref(), context, &origin(headers));
+
+    let disposition = format!("inline; filename=\"{}.jsonld\"", export::slug(&model));
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/ld+json".to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        Json(json),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use crate::jobs::fetch_reel::ReelInfo;
+    use clap::Parser;
+    use sea_orm::prelude::DateTimeWithTimeZone;
+
+    #[test]
+    fn describes_a_recipe_from_a_reel() {
+        let reel_dir = tempfile::tempdir().unwrap();
+        std::fs::write(reel_dir.path().join("ABC.jpg"), b"").unwrap();
+
+        let cli = Cli::parse_from([
+            "recipes-rs".as_ref(),
+            "--reel-dir".as_ref(),
+            reel_dir.path().as_os_str(),
+            "serve".as_ref(),
+        ]);
+        let context = JobContext::new(DatabaseConnection::Disconnected, &cli);
+
+        let recipe_model = recipes::Model {
+            id: 7,
+            title: Some("Lemon pasta".to_string()),
+            ingredients: Some(vec!["200g spaghetti".to_string(), "1 lemon".to_string()]),
+            instructions: Some(vec![
+                "Boil the spaghetti".to_string(),
+                "Zest the lemon over it".to_string(),
+            ]),
+            updated_at: DateTimeWithTimeZone::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap(),
+            instagram_video_id: Some(3),
+            generated_at: None,
+            owner_id: Some(1),
+            household_id: Some(1),
+            servings: Some("2".to_string()),
+            prep_minutes: Some(5),
+            cook_minutes: Some(60),
+            notes: Some("Weeknight favourite".to_string()),
+            source_url: None,
+            image: None,
+        };
+
+        let video = instagram_video::Model {
+            id: 3,
+            instagram_id: "ABC".to_string(),
+            video_url: "https://www.instagram.com/reel/ABC/".to_string(),
+            info: ReelInfo::from_description("Pasta in 10 minutes".to_string()),
+            created_at: DateTimeWithTimeZone::parse_from_rfc3339("2024-04-30T08:00:00Z")
+                .ok()
+                .map(|date| date.naive_utc()),
+            updated_at: None,
+            transcript: None,
+            owner_id: Some(1),
+            on_screen_text: None,
+            images: None,
+        };
+
+        assert_eq!(
+            recipe(
+                &recipe_model,
+                Some(&video),
+                &context,
+                "https://recipes.example"
+            ),
+            json!({
+                "@context": "https://schema.org",
+                "@type": "Recipe",
+                "@id": "https://recipes.example/recipes/7",
+                "url": "https://recipes.example/recipes/7",
+                "name": "Lemon pasta",
+                "recipeYield": "2",
+                "prepTime": "PT5M",
+                "cookTime": "PT1H",
+                "totalTime": "PT1H5M",
+                "recipeIngredient": ["200g spaghetti", "1 lemon"],
+                "recipeInstructions": [
+                    { "@type": "HowToStep", "position": 1, "text": "Boil the spaghetti" },
+                    { "@type": "HowToStep", "position": 2, "text": "Zest the lemon over it" },
+                ],
+                "description": "Weeknight favourite",
+                "isBasedOn": "https://www.instagram.com/reel/ABC/",
+                "video": {
+                    "@type": "VideoObject",
+                    "name": "Lemon pasta",
+                    "description": "Pasta in 10 minutes",
+                    "contentUrl": "https://recipes.example/videos/ABC",
+                    "thumbnailUrl": "https://recipes.example/videos/ABC/thumbnail",
+                    "uploadDate": "2024-04-30T08:00:00+00:00",
+                },
+                "image": ["https://recipes.example/videos/ABC/thumbnail"],
+            })
+        );
+    }
+}
@@ -1,51 +1,79 @@
+mod alignment;
+mod api;
+mod auth;
+mod captions;
 mod cli;
+mod collections;
 mod entities;
 mod error;
+mod export;
+mod health;
+mod households;
+mod imports;
+mod interop;
 mod jobs;
-
+mod jsonld;
+mod negotiate;
+mod openapi;
+mod print;
+mod share;
+mod telemetry;
+mod uploads;
+
+use crate::auth::CurrentUser;
+use crate::entities::prelude::Recipes;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
 use crate::jobs::{JobContext, JOB_CONTEXT};
 use anyhow::anyhow;
 use async_trait::async_trait;
 use axum::body::Body;
 use axum::extract::rejection::{FormRejection, JsonRejection};
-use axum::extract::{FromRequest, Path, Request};
-use axum::http::header::{ACCEPT, CONTENT_TYPE};
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::extract::{DefaultBodyLimit, FromRequest, Path, Query, Request};
+use axum::handler::Handler;
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Redirect, Response};
 use axum::{
+    middleware,
     routing::{get, post},
     Extension, Form, Json, Router,
 };
 use axum_extra::routing::Resource;
 use clap::Parser;
-use cli::Cli;
-use fang::{AsyncQueue, AsyncQueueable, AsyncWorkerPool, NoTls, Serialize};
-use jobs::fetch_reel::FetchReelJob;
+use cli::{Cli, Command, ServeArgs};
+use error::AppError;
+use fang::{AsyncQueue, NoTls, Serialize};
+use jobs::fetch_post::FetchJob;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::services::{ServeDir, ServeFile};
+use tower_http::trace::TraceLayer;
 
 use crate::jobs::llm_extract_details::LLmExtractDetailsJob;
 use axum_template::{engine::Engine, RenderHtml};
 use jobs::extract_transcript::ExtractTranscriptJob;
+use metrics_exporter_prometheus::PrometheusHandle;
 use minijinja::{path_loader, Environment};
 use minijinja_autoreload::AutoReloader;
 use notify::Watcher;
 use sea_orm::DatabaseBackend::Postgres;
 use sea_orm::{
-    ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult, QuerySelect, Statement,
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter,
+    QuerySelect, Statement,
 };
 use serde::de::DeserializeOwned;
 use sqlx::PgPool;
 use tower_livereload::LiveReloadLayer;
+use utoipa::ToSchema;
 
 type FangQueue = AsyncQueue<NoTls>;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
     let cli = Cli::parse();
+    telemetry::init_tracing(cli.otlp_endpoint.as_deref())?;
     cli.validate_reel_dir()?;
 
     let db = PgPool::connect(&cli.database_url).await?;
@@ -55,15 +83,86 @@ async fn main() -> anyhow::Result<()> {
 
     let mut queue = AsyncQueue::builder()
         .uri(&cli.database_url)
-        .max_pool_size(2u32)
+        .max_pool_size(cli.queue_pool_size)
         .build();
 
     queue.connect(NoTls).await?;
 
-    let job_context = JobContext::new(seaorm.clone(), db.clone(), &cli);
+    let job_context = JobContext::new(seaorm.clone(), &cli);
 
     JOB_CONTEXT.set(job_context.clone()).unwrap();
 
+    let metrics = telemetry::install_recorder()?;
+
+    let result = match &cli.command {
+        Command::Serve(args) => serve(args, seaorm, db, queue, job_context, metrics).await,
+
+        Command::Worker(args) => {
+            jobs::start_worker_pools(&queue, args).await;
+
+            match args.metrics_address {
+                Some(address) => serve_metrics(address, job_context, metrics).await,
+                None => {
+                    shutdown_signal().await;
+                    Ok(())
+                }
+            }
+        }
+
+        Command::All {
+            serve: serve_args,
+            worker,
+        } => {
+            jobs::start_worker_pools(&queue, worker).await;
+            serve(serve_args, seaorm, db, queue, job_context, metrics).await
+        }
+
+        Command::CreateUser(args) => auth::create_user(&seaorm, args).await,
+
+        Command::ImportInstagram(args) => imports::import_instagram(&seaorm, args).await,
+
+        Command::ImportRecipes(args) => interop::import_file(&seaorm, &job_context, args).await,
+
+        Command::ExportRecipes(args) => interop::export_file(&seaorm, &job_context, args).await,
+
+        Command::ExtractThumbnails => {
+            jobs::extract_thumbnail::queue_missing(&seaorm, &job_context, &mut queue).await
+        }
+    };
+
+    telemetry::shutdown_tracing();
+    result
+}
+
+/// Standalone `/metrics` listener for worker processes, which otherwise have no server.
+async fn serve_metrics(
+    address: SocketAddr,
+    job_context: JobContext,
+    metrics: PrometheusHandle,
+) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(telemetry::metrics))
+        .layer(Extension(job_context))
+        .layer(Extension(metrics));
+
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    tracing::info!("Serving metrics on {}", address);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn serve(
+    args: &ServeArgs,
+    seaorm: DatabaseConnection,
+    db: PgPool,
+    queue: FangQueue,
+    job_context: JobContext,
+    metrics: PrometheusHandle,
+) -> anyhow::Result<()> {
     // Set up the `minijinja` engine with the same route paths as the Axum router
     let jinja = AutoReloader::new(move |notifier| {
         let template_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("./app/views");
@@ -93,16 +192,106 @@ async fn main() -> anyhow::Result<()> {
     let app = Router::new()
         .merge(recipes)
         .merge(videos)
+        .merge(api::router())
         .route("/", get(|| async { Redirect::to("/recipes") }))
+        .route("/health", get(health::health))
+        .route("/ready", get(health::ready))
+        .route("/metrics", get(telemetry::metrics))
+        .route("/api/openapi.json", get(openapi::openapi_json))
+        .merge(openapi::swagger_ui())
+        .route("/manifest.webmanifest", get(share::manifest))
+        .route("/service-worker.js", get(share::service_worker))
+        .route("/icon.svg", get(share::icon))
+        .route("/share", get(share::confirm_share).post(share::share))
+        .route("/recipes/text", post(create_recipe_from_text))
+        .route("/recipes/export.zip", get(export::export_library))
+        .route("/recipes/export/:app", get(interop::export_recipes))
+        .route("/recipes/:recipes_id/print", get(print::print_recipe))
+        .route("/recipes/:recipes_id/image", get(get_recipe_image))
+        .route("/videos/:videos_id/thumbnail", get(get_thumbnail))
+        .route("/videos/:videos_id/images/:name", get(get_post_image))
+        .route(
+            "/videos/:videos_id/captions.vtt",
+            get(captions::webvtt_captions),
+        )
+        .route(
+            "/videos/:videos_id/captions.srt",
+            get(captions::srt_captions),
+        )
+        .route(
+            "/imports",
+            get(imports::imports_index)
+                .post(imports::create_import)
+                .layer(DefaultBodyLimit::max(imports::MAX_UPLOAD_BYTES)),
+        )
+        .route(
+            "/imports/recipes",
+            post(interop::import_recipes).layer(DefaultBodyLimit::max(imports::MAX_UPLOAD_BYTES)),
+        )
+        .route("/imports/:id", get(imports::show_import))
+        .route(
+            "/videos",
+            post(uploads::upload_video).layer(DefaultBodyLimit::max(
+                args.max_video_upload_mb as usize * 1024 * 1024 + uploads::FORM_OVERHEAD_BYTES,
+            )),
+        )
+        .route("/login", get(auth::login_page).post(auth::login))
+        .route("/logout", post(auth::logout))
+        .route(
+            "/settings/tokens",
+            get(api::tokens::tokens_index).post(api::tokens::create_token),
+        )
+        .route(
+            "/settings/tokens/:id/revoke",
+            post(api::tokens::revoke_token),
+        )
+        .route("/household", get(households::show_household))
+        .route(
+            "/household/invitations",
+            post(households::create_invitation),
+        )
+        .route(
+            "/household/members/:user_id",
+            post(households::update_member),
+        )
+        .route(
+            "/household/members/:user_id/remove",
+            post(households::remove_member),
+        )
+        .route(
+            "/invitations/:token",
+            get(households::show_invitation).post(households::accept_invitation),
+        )
         .route("/videos/:id/llm", post(llm))
         .route("/videos/:id/transcribe", post(transcribe_video))
+        .route("/failures", get(failures_index))
         .nest_service("/public", ServeDir::new("./public"))
-        .layer(Extension(seaorm.clone()))
-        .layer(Extension(db.clone()))
-        .layer(Extension(queue.clone()))
-        .layer(Extension(template_engine))
+        // `route_layer`s don't see unmatched requests, so the fallback renders its own error
+        .fallback(error::not_found.layer(middleware::from_fn_with_state(
+            template_engine.clone(),
+            error::render_errors,
+        )))
+        .layer(Extension(seaorm))
+        .layer(Extension(db))
+        .layer(Extension(queue))
+        .layer(Extension(template_engine.clone()))
         .layer(Extension(job_context))
-        .layer(livereload);
+        .layer(Extension(metrics))
+        .layer(Extension(imports::ImportSettings {
+            interval: std::time::Duration::from_secs(args.import_interval),
+        }))
+        .layer(Extension(uploads::UploadSettings {
+            max_video_bytes: args.max_video_upload_mb * 1024 * 1024,
+        }))
+        .layer(livereload)
+        .route_layer(middleware::from_fn_with_state(
+            template_engine,
+            error::render_errors,
+        ))
+        .route_layer(middleware::from_fn(telemetry::track_http))
+        .route_layer(TraceLayer::new_for_http().make_span_with(telemetry::make_http_span))
+        .route_layer(PropagateRequestIdLayer::x_request_id())
+        .route_layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     let mut watcher = notify::recommended_watcher(move |_| {
         tracing::info!("Reloading...");
@@ -116,17 +305,9 @@ async fn main() -> anyhow::Result<()> {
 
     watcher.watch(&PathBuf::from("./public"), notify::RecursiveMode::Recursive)?;
 
-    let mut pool: AsyncWorkerPool<AsyncQueue<NoTls>> = AsyncWorkerPool::builder()
-        .number_of_workers(2u32)
-        .queue(queue)
-        .build();
-
-    // This await does nothing, the method is entirely synchronous
-    pool.start().await;
-
     // run our app with hyper, listening globally on port 3000
-    let listener = tokio::net::TcpListener::bind(&cli.address).await.unwrap();
-    tracing::info!("Listening on {}", cli.address);
+    let listener = tokio::net::TcpListener::bind(&args.address).await.unwrap();
+    tracing::info!("Listening on {}", args.address);
 
     axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
@@ -135,111 +316,339 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Queue the video to be transcribed again.
+#[utoipa::path(
+    post,
+    path = "/videos/{id}/transcribe",
+    tag = "videos",
+    params(("id" = u32, Path, description = "Video id")),
+    responses(
+        (status = 201, description = "Transcription queued"),
+        (status = 404, description = "Unknown video id"),
+        (status = 403, description = "Viewers can't queue work"),
+    )
+)]
 async fn transcribe_video(
+    user: CurrentUser,
     Path((id,)): Path<(u32,)>,
-    Extension(mut jobs): Extension<FangQueue>,
+    Extension(mut queue): Extension<FangQueue>,
     Extension(db): Extension<DatabaseConnection>,
 ) -> error::Result<impl IntoResponse> {
-    return match ExtractTranscriptJob::new(id as i32, &db).await {
+    user.require(HouseholdRole::Editor)?;
+
+    let Some(id) = api::find_visible_video(&db, user.household_id, id as i32).await? else {
+        return Err(AppError::NotFound("Video"));
+    };
+
+    match ExtractTranscriptJob::new(id, &db).await {
         Ok(job) => {
-            jobs.insert_task(&job).await?;
+            jobs::enqueue(&mut queue, &db, &job).await?;
             Ok(StatusCode::CREATED.into_response())
         }
 
-        Err(_) => Ok((StatusCode::BAD_REQUEST, "Invalid video id").into_response()),
-    };
+        Err(_) => Err(AppError::NotFound("Video")),
+    }
 }
 
+/// Queue recipe extraction from the video's transcript.
+#[utoipa::path(
+    post,
+    path = "/videos/{id}/llm",
+    tag = "videos",
+    params(("id" = u32, Path, description = "Video id")),
+    responses(
+        (status = 201, description = "Id of the queued job", body = String),
+        (status = 404, description = "Unknown video id"),
+        (status = 403, description = "Viewers can't queue work"),
+    )
+)]
 async fn llm(
+    user: CurrentUser,
     Path((id,)): Path<(u32,)>,
-    Extension(mut jobs): Extension<FangQueue>,
+    Extension(mut queue): Extension<FangQueue>,
+    Extension(db): Extension<DatabaseConnection>,
 ) -> error::Result<impl IntoResponse> {
-    let job = LLmExtractDetailsJob {
-        video_id: id as i32,
+    user.require(HouseholdRole::Editor)?;
+
+    let Some(id) = api::find_visible_video(&db, user.household_id, id as i32).await? else {
+        return Err(AppError::NotFound("Video"));
     };
 
-    let job = jobs.insert_task(&job).await?;
+    let job = LLmExtractDetailsJob::new(id, Some(user.id), Some(user.household_id));
+
+    let job = jobs::enqueue(&mut queue, &db, &job).await?;
     Ok((StatusCode::CREATED, job.id.to_string()).into_response())
 }
 
+#[derive(Deserialize, Debug)]
+struct RecipesIndexQuery {
+    /// Set when redirected here after sharing a reel to the app.
+    #[serde(default)]
+    shared: bool,
+
+    /// Set when redirected here after uploading a video.
+    #[serde(default)]
+    uploaded: bool,
+
+    /// Set to the number of recipes added when redirected here after importing from another app.
+    imported: Option<usize>,
+
+    /// Only show recipes in this collection.
+    collection: Option<i32>,
+}
+
 #[derive(Serialize, FromQueryResult)]
 struct RecipeIdTitle {
     id: i32,
     title: String,
+    /// Reel the recipe came from, for its thumbnail.
+    instagram_id: Option<String>,
+    /// The recipe's own photo, preferred over the reel's thumbnail.
+    image: Option<String>,
 }
 
+/// The household's recipes, as a page or as JSON depending on `Accept`.
+#[utoipa::path(
+    get,
+    path = "/recipes",
+    tag = "recipes",
+    params(("collection" = Option<i32>, Query, description = "Only recipes in this collection")),
+    responses(
+        (status = 200, content(
+            (Vec<entities::recipes::Model> = "application/json"),
+            ("text/html"),
+        )),
+        (status = 303, description = "Not logged in, redirected to `/login`"),
+    )
+)]
 async fn recipes_index(
+    user: CurrentUser,
     header_map: HeaderMap,
+    Query(query): Query<RecipesIndexQuery>,
     Extension(template_engine): Extension<Engine<AutoReloader>>,
     Extension(db): Extension<DatabaseConnection>,
 ) -> error::Result<impl IntoResponse> {
-    match header_map.get(ACCEPT) {
-        Some(hv) if is_json(hv) => {
-            let recipes = entities::recipes::Entity::find().all(&db).await?;
+    user.require(HouseholdRole::Viewer)?;
 
-            Ok(Json(recipes).into_response())
-        }
+    let mut owned =
+        Recipes::find().filter(entities::recipes::Column::HouseholdId.eq(user.household_id));
 
-        _ => {
-            let recipes = entities::recipes::Entity::find()
-                .select_only()
-                .columns([
-                    entities::recipes::Column::Id,
-                    entities::recipes::Column::Title,
-                ])
-                .into_model::<RecipeIdTitle>()
-                .all(&db)
-                .await?;
-
-            Ok(RenderHtml(
-                "recipes/index.html",
-                template_engine,
-                json!({ "recipes": recipes }),
-            )
-            .into_response())
-        }
+    if let Some(collection) = query.collection {
+        owned = owned
+            .inner_join(entities::prelude::CollectionRecipes)
+            .filter(entities::collection_recipes::Column::CollectionId.eq(collection));
+    }
+
+    if negotiate::wants_json(&header_map) {
+        let recipes = owned.all(&db).await?;
+
+        Ok(Json(recipes).into_response())
+    } else {
+        let recipes = owned
+            .select_only()
+            .columns([
+                entities::recipes::Column::Id,
+                entities::recipes::Column::Title,
+                entities::recipes::Column::Image,
+            ])
+            .column(entities::instagram_video::Column::InstagramId)
+            .left_join(entities::prelude::InstagramVideo)
+            .into_model::<RecipeIdTitle>()
+            .all(&db)
+            .await?;
+
+        let collections = collections::list(&db, user.household_id).await?;
+
+        Ok(RenderHtml(
+            "recipes/index.html",
+            template_engine,
+            json!({
+                "recipes": recipes,
+                "shared": query.shared,
+                "uploaded": query.uploaded,
+                "imported": query.imported,
+                "collections": collections,
+                "collection": query.collection,
+                "user": {
+                    "username": user.username,
+                    "can_edit": user.require(HouseholdRole::Editor).is_ok(),
+                },
+            }),
+        )
+        .into_response())
     }
 }
 
-async fn load_nested_recipe(recipe_id: i32, db: &DatabaseConnection) -> anyhow::Result<Value> {
-    let recipe = db
+#[derive(Serialize, FromQueryResult, ToSchema)]
+struct FailedVideo {
+    video_id: i32,
+    instagram_id: String,
+    video_url: String,
+    reason: String,
+    failed_at: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// Videos whose LLM extraction failed permanently, most recent first.
+#[utoipa::path(
+    get,
+    path = "/failures",
+    tag = "videos",
+    responses(
+        (status = 200, content(
+            (Vec<FailedVideo> = "application/json"),
+            ("text/html"),
+        )),
+    )
+)]
+async fn failures_index(
+    user: CurrentUser,
+    header_map: HeaderMap,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let failures = FailedVideo::find_by_statement(Statement::from_sql_and_values(
+        Postgres,
+        r#"
+        select iv.id as video_id, iv.instagram_id, iv.video_url, f.reason, f.failed_at
+from llm_extraction_failures f
+         join public.instagram_video iv on iv.id = f.instagram_video_id
+where f.household_id = $1
+order by f.failed_at desc;
+        "#,
+        [user.household_id.into()],
+    ))
+    .all(&db)
+    .await?;
+
+    if negotiate::wants_json(&header_map) {
+        Ok(Json(failures).into_response())
+    } else {
+        Ok(RenderHtml(
+            "failures/index.html",
+            template_engine,
+            json!({ "failures": failures }),
+        )
+        .into_response())
+    }
+}
+
+/// The shape of the JSON built by [`load_nested_recipe`], only used to document it.
+#[derive(Serialize, ToSchema)]
+#[allow(dead_code)]
+struct NestedRecipe {
+    #[serde(flatten)]
+    recipe: entities::recipes::Model,
+    instagram_video: Option<entities::instagram_video::Model>,
+    /// Seconds into the video each instruction is described at, `null` for steps that couldn't
+    /// be matched to the transcript. Empty without a transcript.
+    step_times: Vec<Option<f64>>,
+}
+
+async fn load_nested_recipe(
+    recipe_id: i32,
+    household_id: i32,
+    db: &DatabaseConnection,
+) -> error::Result<Value> {
+    let mut recipe = db
         .query_one(Statement::from_sql_and_values(
             Postgres,
             r#"
         select to_jsonb(r) || jsonb_build_object('instagram_video', to_jsonb(iv)) as json
 from recipes r
          left join public.instagram_video iv on iv.id = r.instagram_video_id
-where r.id = $1;
+where r.id = $1
+  and r.household_id = $2;
         "#,
-            vec![recipe_id.into()],
+            vec![recipe_id.into(), household_id.into()],
         ))
         .await?
-        .ok_or(anyhow!("Unknown recipe id"))?
+        .ok_or(AppError::NotFound("Recipe"))?
         .try_get_by::<Value, _>("json")?;
 
+    alignment::annotate(&mut recipe);
     Ok(recipe)
 }
 
+/// A recipe with the video it came from nested. With an extension on the id it's served as a
+/// file instead: `.pdf` for a one page A5 card, `.jsonld` for its schema.org `Recipe`, or `.md`,
+/// `.cook` and `.txt` in the export formats.
+#[utoipa::path(
+    get,
+    path = "/recipes/{id}",
+    tag = "recipes",
+    params((
+        "id" = String,
+        Path,
+        description = "Recipe id, optionally with `.pdf`, `.jsonld`, `.md`, `.cook` or `.txt`",
+    )),
+    responses(
+        (status = 200, content(
+            (NestedRecipe = "application/json"),
+            ("text/html"),
+            ("application/pdf"),
+            ("application/ld+json"),
+            ("text/plain"),
+        )),
+        (status = 404, description = "No such recipe in the household, or unknown format"),
+    )
+)]
 async fn show_recipe(
+    user: CurrentUser,
     header_map: HeaderMap,
     Extension(template_engine): Extension<Engine<AutoReloader>>,
     Extension(db): Extension<DatabaseConnection>,
-    Path((recipe_id,)): Path<(u32,)>,
-) -> impl IntoResponse {
-    let Ok(recipe) = load_nested_recipe(recipe_id as i32, &db).await else {
-        return (StatusCode::NOT_FOUND, "Recipe not found").into_response();
+    Extension(context): Extension<JobContext>,
+    Path((recipe_id,)): Path<(String,)>,
+) -> error::Result<Response> {
+    user.require(HouseholdRole::Viewer)?;
+
+    // `/recipes/12.md` and friends share the route, the extension picks the export format
+    let (recipe_id, extension) = match recipe_id.split_once('.') {
+        Some((recipe_id, extension)) => (recipe_id, Some(extension)),
+        None => (recipe_id.as_str(), None),
     };
 
-    match header_map.get(ACCEPT) {
-        Some(hv) if is_json(hv) => Json(recipe).into_response(),
-        _ => RenderHtml("recipes/show.html", template_engine, recipe).into_response(),
+    let recipe_id = recipe_id
+        .parse::<u32>()
+        .map_err(|_| AppError::NotFound("Recipe"))? as i32;
+
+    match extension {
+        None => {}
+        Some("pdf") => return print::pdf_recipe(&db, user.household_id, recipe_id).await,
+        Some("jsonld") => {
+            return jsonld::jsonld_recipe(&db, &context, &header_map, user.household_id, recipe_id)
+                .await
+        }
+        Some(extension) => {
+            let format = export::Format::from_extension(extension)
+                .ok_or(AppError::NotFound("Export format"))?;
+
+            return export::export_recipe(&db, user.household_id, recipe_id, format).await;
+        }
     }
-}
 
-fn is_json(hv: &HeaderValue) -> bool {
-    hv.to_str()
-        .map(|hv| hv == "application/json")
-        .unwrap_or(false)
+    let mut recipe = load_nested_recipe(recipe_id, user.household_id, &db).await?;
+
+    if negotiate::wants_json(&header_map) {
+        Ok(Json(recipe).into_response())
+    } else {
+        // The JSON-LD is built from the same row rather than loading the recipe again
+        let model: entities::recipes::Model =
+            serde_json::from_value(recipe.clone()).map_err(anyhow::Error::from)?;
+        let video: Option<entities::instagram_video::Model> =
+            serde_json::from_value(recipe["instagram_video"].clone())
+                .map_err(anyhow::Error::from)?;
+        recipe["json_ld"] = jsonld::recipe(
+            &model,
+            video.as_ref(),
+            &context,
+            &jsonld::origin(&header_map),
+        );
+
+        Ok(RenderHtml("recipes/show.html", template_engine, recipe).into_response())
+    }
 }
 
 async fn shutdown_signal() {
@@ -248,8 +657,9 @@ async fn shutdown_signal() {
         .expect("Failed to install CTRL+C handler");
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct CreateRecipeFromReelRequest {
+    /// A reel, or an image post's `/p/` link.
     reel_url: String,
     auto_llm: Option<bool>,
 }
@@ -277,7 +687,7 @@ where
     type Rejection = Response;
 
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        if req.headers().get(CONTENT_TYPE) == Some(&HeaderValue::from_static("application/json")) {
+        if negotiate::has_json_body(req.headers()) {
             Json::<T>::from_request(req, state)
                 .await
                 .map(|json| FormOrJson::Json(json.0))
@@ -291,27 +701,120 @@ where
     }
 }
 
+/// Queue a reel or image post to be fetched, from the new recipe form or as JSON.
+#[utoipa::path(
+    post,
+    path = "/recipes",
+    tag = "recipes",
+    request_body(content(
+        (CreateRecipeFromReelRequest = "application/json"),
+        (CreateRecipeFromReelRequest = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = 201, description = "Fetch queued"),
+        (status = 400, description = "Not an Instagram reel or post URL"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
 async fn create_recipe_from_reel(
+    user: CurrentUser,
     Extension(mut queue): Extension<FangQueue>,
+    Extension(db): Extension<DatabaseConnection>,
     request: FormOrJson<CreateRecipeFromReelRequest>,
 ) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Editor)?;
     let request = request.into_inner();
 
-    return match FetchReelJob::new(request.reel_url, request.auto_llm.unwrap_or(false)) {
+    match FetchJob::new(
+        request.reel_url,
+        request.auto_llm.unwrap_or(false),
+        user.id,
+        user.household_id,
+    ) {
         Ok(job) => {
-            queue.insert_task(&job).await?;
+            jobs::enqueue(&mut queue, &db, job.job()).await?;
             Ok(StatusCode::CREATED.into_response())
         }
 
-        Err(_) => Ok((StatusCode::BAD_REQUEST, "Invalid reel URL").into_response()),
-    };
+        Err(e) => Err(AppError::BadRequest(e)),
+    }
+}
+
+/// Pasted text is cut off here, a recipe is never this long and the prompt has to fit the model.
+const MAX_RECIPE_TEXT_CHARS: usize = 20_000;
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct CreateRecipeFromTextRequest {
+    /// A recipe as written in an email, message or note.
+    text: String,
+}
+
+/// Queue recipe extraction from pasted text, through the same prompt as reels.
+#[utoipa::path(
+    post,
+    path = "/recipes/text",
+    tag = "recipes",
+    request_body(content(
+        (CreateRecipeFromTextRequest = "application/json"),
+        (CreateRecipeFromTextRequest = "application/x-www-form-urlencoded"),
+    )),
+    responses(
+        (status = 201, description = "Extraction queued"),
+        (status = 400, description = "No text"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
+async fn create_recipe_from_text(
+    user: CurrentUser,
+    Extension(mut queue): Extension<FangQueue>,
+    Extension(db): Extension<DatabaseConnection>,
+    request: FormOrJson<CreateRecipeFromTextRequest>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Editor)?;
+    let job = text_extraction_job(request.into_inner().text, &user)?;
+
+    jobs::enqueue(&mut queue, &db, &job).await?;
+    Ok(StatusCode::CREATED.into_response())
 }
 
+/// Extraction job for pasted `text`, shared with `/api/v1`.
+pub(crate) fn text_extraction_job(
+    text: String,
+    user: &CurrentUser,
+) -> error::Result<LLmExtractDetailsJob> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return Err(AppError::BadRequest(anyhow!("No recipe text given")));
+    }
+
+    let text: String = text.chars().take(MAX_RECIPE_TEXT_CHARS).collect();
+    Ok(LLmExtractDetailsJob::from_text(
+        text,
+        user.id,
+        user.household_id,
+    ))
+}
+
+/// The downloaded video file, supporting range requests.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}",
+    tag = "videos",
+    params(("id" = String, Path, description = "Instagram id of the reel")),
+    responses(
+        (status = 200, description = "The video", content_type = "video/mp4"),
+        (status = 206, description = "Part of the video", content_type = "video/mp4"),
+    )
+)]
 async fn get_video(
+    user: CurrentUser,
     Extension(context): Extension<JobContext>,
     headers: HeaderMap,
     Path((instagram_id,)): Path<(String,)>,
 ) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
     let video_path = context.video_path(&instagram_id);
 
     let mut req = Request::new(Body::empty());
@@ -321,3 +824,116 @@ async fn get_video(
         .await
         .map_err(|e| anyhow!(e))?)
 }
+
+/// Plain file names only, for path parts that come from the URL, so nothing can reach outside
+/// the reel directory.
+fn is_plain_file_name(part: &str) -> bool {
+    !part.starts_with('.')
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// One of an image post's images, by the file name listed in the video's `images`.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}/images/{name}",
+    tag = "videos",
+    params(
+        ("id" = String, Path, description = "Instagram id of the post"),
+        ("name" = String, Path, description = "File name of the image"),
+    ),
+    responses(
+        (status = 200, description = "The image", content_type = "image/*"),
+        (status = 404, description = "No such image"),
+    )
+)]
+async fn get_post_image(
+    user: CurrentUser,
+    Extension(context): Extension<JobContext>,
+    headers: HeaderMap,
+    Path((instagram_id, name)): Path<(String, String)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let image_path = context.post_dir(&instagram_id).join(&name);
+
+    if !is_plain_file_name(&instagram_id) || !is_plain_file_name(&name) || !image_path.is_file() {
+        return Err(AppError::NotFound("Image"));
+    }
+
+    let mut req = Request::new(Body::empty());
+    *req.headers_mut() = headers;
+    Ok(ServeFile::new(image_path)
+        .try_call(req)
+        .await
+        .map_err(|e| anyhow!(e))?)
+}
+
+/// A recipe's own photo, for recipes imported from another app with one.
+#[utoipa::path(
+    get,
+    path = "/recipes/{id}/image",
+    tag = "recipes",
+    params(("id" = u32, Path, description = "Recipe id")),
+    responses(
+        (status = 200, description = "The photo", content_type = "image/*"),
+        (status = 404, description = "No such recipe in the household, or it has no photo"),
+    )
+)]
+async fn get_recipe_image(
+    user: CurrentUser,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(context): Extension<JobContext>,
+    headers: HeaderMap,
+    Path((recipe_id,)): Path<(i32,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let (recipe, _) = export::load_recipe(&db, user.household_id, recipe_id).await?;
+    let image_path = recipe
+        .image
+        .map(|image| context.recipe_image_dir().join(image))
+        .filter(|path| path.exists())
+        .ok_or(AppError::NotFound("Recipe image"))?;
+
+    let mut req = Request::new(Body::empty());
+    *req.headers_mut() = headers;
+    Ok(ServeFile::new(image_path)
+        .try_call(req)
+        .await
+        .map_err(|e| anyhow!(e))?)
+}
+
+/// The poster frame shown on the video's recipe cards.
+#[utoipa::path(
+    get,
+    path = "/videos/{id}/thumbnail",
+    tag = "videos",
+    params(("id" = String, Path, description = "Instagram id of the reel")),
+    responses(
+        (status = 200, description = "The thumbnail", content_type = "image/jpeg"),
+        (status = 404, description = "No thumbnail extracted yet"),
+    )
+)]
+async fn get_thumbnail(
+    user: CurrentUser,
+    Extension(context): Extension<JobContext>,
+    headers: HeaderMap,
+    Path((instagram_id,)): Path<(String,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let thumbnail_path = context.thumbnail_path(&instagram_id);
+
+    if !is_plain_file_name(&instagram_id) || !thumbnail_path.is_file() {
+        return Err(AppError::NotFound("Thumbnail"));
+    }
+
+    let mut req = Request::new(Body::empty());
+    *req.headers_mut() = headers;
+    Ok(ServeFile::new(thumbnail_path)
+        .try_call(req)
+        .await
+        .map_err(|e| anyhow!(e))?)
+}
@@ -0,0 +1,168 @@
+//! Content negotiation between our HTML views and their JSON representations.
+
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::HeaderMap;
+
+pub const HTML: &str = "text/html";
+pub const JSON: &str = "application/json";
+
+struct MediaRange<'a> {
+    essence: &'a str,
+    q: f32,
+}
+
+impl MediaRange<'_> {
+    /// How specifically this range matches `offered`, `None` if it doesn't match at all.
+    fn specificity(&self, offered: &str) -> Option<u8> {
+        if self.essence.eq_ignore_ascii_case(offered) {
+            return Some(2);
+        }
+
+        match self.essence.split_once('/') {
+            Some(("*", "*")) => Some(0),
+            Some((kind, "*")) => offered
+                .split_once('/')
+                .filter(|(offered_kind, _)| offered_kind.eq_ignore_ascii_case(kind))
+                .map(|_| 1),
+            _ => None,
+        }
+    }
+}
+
+fn parse_accept(headers: &HeaderMap) -> Vec<MediaRange<'_>> {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|hv| hv.to_str().ok())
+        .flat_map(|hv| hv.split(','))
+        .filter_map(|range| {
+            let mut params = range.split(';').map(str::trim);
+            let essence = params.next().filter(|essence| !essence.is_empty())?;
+
+            let q = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            Some(MediaRange { essence, q })
+        })
+        .collect()
+}
+
+/// Pick the type from `offered` the client prefers, honouring quality values and wildcards. Ties go
+/// to whichever comes first in `offered`, and a missing `Accept` header accepts anything.
+pub fn preferred<'a>(headers: &HeaderMap, offered: &[&'a str]) -> Option<&'a str> {
+    let ranges = parse_accept(headers);
+
+    if ranges.is_empty() {
+        return offered.first().copied();
+    }
+
+    let mut best: Option<(&str, f32)> = None;
+
+    for &candidate in offered {
+        // The most specific matching range decides the quality, so `text/html;q=0` beats `*/*`
+        let q = ranges
+            .iter()
+            .filter_map(|range| range.specificity(candidate).map(|s| (s, range.q)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, q)| q)
+            .unwrap_or(0.0);
+
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((candidate, q));
+        }
+    }
+
+    best.map(|(candidate, _)| candidate)
+}
+
+/// Whether to respond with JSON rather than HTML. Browsers send `*/*` alongside `text/html` so
+/// they still get HTML, while `Accept: application/json` (with any parameters) gets JSON.
+pub fn wants_json(headers: &HeaderMap) -> bool {
+    preferred(headers, &[HTML, JSON]) == Some(JSON)
+}
+
+/// Whether the request body is JSON, ignoring parameters such as `charset`.
+pub fn has_json_body(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|hv| hv.to_str().ok())
+        .and_then(|hv| hv.split(';').next())
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(JSON))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn parses_quality_values() {
+        let headers = accept("text/html, application/json;q=0.5, */*; Q = 0.1, ;q=1");
+        let ranges: Vec<_> = parse_accept(&headers)
+            .iter()
+            .map(|range| (range.essence, range.q))
+            .collect();
+
+        assert_eq!(
+            ranges,
+            [("text/html", 1.0), ("application/json", 0.5), ("*/*", 0.1)]
+        );
+    }
+
+    #[test]
+    fn unparseable_quality_counts_as_one() {
+        let headers = accept("application/json;q=high");
+        assert_eq!(parse_accept(&headers)[0].q, 1.0);
+    }
+
+    #[test]
+    fn highest_quality_wins() {
+        let headers = accept("text/html;q=0.4, application/json;q=0.9");
+        assert_eq!(preferred(&headers, &[HTML, JSON]), Some(JSON));
+    }
+
+    #[test]
+    fn ties_go_to_the_first_offered() {
+        let headers = accept("application/json, text/html");
+        assert_eq!(preferred(&headers, &[HTML, JSON]), Some(HTML));
+    }
+
+    #[test]
+    fn specific_range_overrides_wildcard() {
+        let headers = accept("*/*, text/html;q=0");
+        assert_eq!(preferred(&headers, &[HTML, JSON]), Some(JSON));
+    }
+
+    #[test]
+    fn type_wildcard_matches_its_subtypes() {
+        let headers = accept("application/*");
+        assert_eq!(preferred(&headers, &[HTML, JSON]), Some(JSON));
+    }
+
+    #[test]
+    fn nothing_acceptable() {
+        let headers = accept("image/png");
+        assert_eq!(preferred(&headers, &[HTML, JSON]), None);
+    }
+
+    #[test]
+    fn missing_header_accepts_anything() {
+        assert_eq!(preferred(&HeaderMap::new(), &[HTML, JSON]), Some(HTML));
+    }
+
+    #[test]
+    fn browsers_get_html() {
+        let headers = accept("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8");
+        assert!(!wants_json(&headers));
+        assert!(wants_json(&accept("application/json; charset=utf-8")));
+    }
+}
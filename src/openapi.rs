@@ -0,0 +1,115 @@
+//! OpenAPI document for every route, generated from the `#[utoipa::path]` annotations on the
+//! handlers themselves.
+
+use crate::{
+    api, auth, captions, export, health, households, imports, interop, print, share, telemetry,
+    uploads,
+};
+use axum::response::IntoResponse;
+use axum::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Recipes",
+        description = "Turns Instagram reels into recipes. Browser routes authenticate with the \
+        session cookie set by `/login`, `/api/v1` with a personal API token."
+    ),
+    paths(
+        crate::recipes_index,
+        crate::create_recipe_from_reel,
+        crate::create_recipe_from_text,
+        crate::show_recipe,
+        export::export_library,
+        print::print_recipe,
+        crate::get_recipe_image,
+        crate::get_video,
+        crate::get_thumbnail,
+        crate::get_post_image,
+        captions::webvtt_captions,
+        captions::srt_captions,
+        uploads::upload_video,
+        crate::llm,
+        crate::transcribe_video,
+        crate::failures_index,
+        health::health,
+        health::ready,
+        telemetry::metrics,
+        auth::login_page,
+        auth::login,
+        auth::logout,
+        households::show_household,
+        households::create_invitation,
+        households::update_member,
+        households::remove_member,
+        households::show_invitation,
+        households::accept_invitation,
+        api::tokens::tokens_index,
+        api::tokens::create_token,
+        api::tokens::revoke_token,
+        share::confirm_share,
+        share::share,
+        imports::imports_index,
+        imports::create_import,
+        imports::show_import,
+        interop::import_recipes,
+        interop::export_recipes,
+        share::manifest,
+        share::service_worker,
+        share::icon,
+        openapi_json,
+    ),
+    nest((path = "/api/v1", api = api::V1Api)),
+    modifiers(&SecuritySchemes),
+    security(("session" = [])),
+    tags(
+        (name = "recipes", description = "The household's recipe library"),
+        (name = "videos", description = "Imported reels and their processing"),
+        (name = "auth", description = "Logging in and out"),
+        (name = "household", description = "Sharing a library between users"),
+        (name = "tokens", description = "Personal API tokens"),
+        (name = "api/v1", description = "Versioned JSON API for scripts"),
+        (name = "pwa", description = "Manifest and service worker for installing the app"),
+        (name = "ops", description = "Health, readiness, metrics and these docs"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(auth::SESSION_COOKIE))),
+        );
+
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// The OpenAPI document itself.
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "ops",
+    security(()),
+    responses((status = 200, description = "OpenAPI 3.1 document", content_type = "application/json"))
+)]
+pub async fn openapi_json() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI rendering `/api/openapi.json`, with its assets bundled into the binary rather than
+/// loaded from a CDN.
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/api/docs").config(Config::from("/api/openapi.json"))
+}
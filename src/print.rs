@@ -0,0 +1,463 @@
+//! A recipe as a single page card for sticking on the fridge, either as a print-optimised page
+//! for the browser's print dialog or as an A5 PDF drawn on the server.
+//!
+//! The PDF uses the standard Helvetica fonts so nothing needs embedding, the catch being there are
+//! no glyph metrics to hand, so lines are wrapped on an average character width. The text is
+//! shrunk until it fits on the one page, and whatever still doesn't is left off.
+
+use crate::auth::CurrentUser;
+use crate::entities::recipes;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::error;
+use crate::export;
+use crate::uploads;
+use anyhow::Context;
+use axum::extract::Path;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use minijinja_autoreload::AutoReloader;
+use printpdf::{
+    BuiltinFont, Color, Greyscale, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect,
+};
+use qrcode::QrCode;
+use sea_orm::DatabaseConnection;
+use serde_json::json;
+
+const PAGE_WIDTH: f32 = 148.0;
+const PAGE_HEIGHT: f32 = 210.0;
+const MARGIN: f32 = 10.0;
+const QR_SIZE: f32 = 26.0;
+
+/// Millimetres per point.
+const PT: f32 = 0.3528;
+
+/// Helvetica's average character width as a share of the font size, a little generous so wrapped
+/// lines don't run off the page.
+const CHAR_WIDTH: f32 = 0.52;
+
+/// The text sizes tried for the ingredients and steps, largest first.
+const BODY_SIZES: &[f32] = &[11.0, 10.0, 9.0, 8.0, 7.0, 6.0];
+
+/// "Serves 4", "Prep 10 min" and "Cook 1 h 5 min", whichever the recipe has.
+pub fn details(recipe: &recipes::Model) -> Vec<String> {
+    let mut details = vec![];
+
+    if let Some(servings) = &recipe.servings {
+        details.push(format!("Serves {servings}"));
+    }
+    if let Some(minutes) = recipe.prep_minutes {
+        details.push(format!("Prep {}", duration(minutes)));
+    }
+    if let Some(minutes) = recipe.cook_minutes {
+        details.push(format!("Cook {}", duration(minutes)));
+    }
+
+    details
+}
+
+pub(crate) fn duration(minutes: i32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes} min"),
+        (hours, 0) => format!("{hours} h"),
+        (hours, minutes) => format!("{hours} h {minutes} min"),
+    }
+}
+
+/// The QR code for `url` as an inline SVG for the print page.
+fn qr_svg(url: &str) -> anyhow::Result<String> {
+    let svg = QrCode::new(url.as_bytes())?
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(160, 160)
+        .build();
+
+    // Drop the XML declaration, the SVG goes straight into the HTML
+    Ok(svg[svg.find("<svg").unwrap_or_default()..].to_string())
+}
+
+/// The recipe laid out as a card for printing from the browser.
+#[utoipa::path(
+    get,
+    path = "/recipes/{id}/print",
+    tag = "recipes",
+    params(("id" = u32, Path, description = "Recipe id")),
+    responses(
+        (status = 200, description = "The printable page", content_type = "text/html"),
+        (status = 404, description = "No such recipe in the household"),
+    )
+)]
+pub async fn print_recipe(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Extension(db): Extension<DatabaseConnection>,
+    Path((recipe_id,)): Path<(u32,)>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Viewer)?;
+
+    let (recipe, video) = export::load_recipe(&db, user.household_id, recipe_id as i32).await?;
+    let source = recipe
+        .source_url
+        .as_deref()
+        .or(video.as_ref().and_then(uploads::source_url));
+    let qr = source.map(qr_svg).transpose()?;
+
+    Ok(RenderHtml(
+        "recipes/print.html",
+        template_engine,
+        json!({
+            "recipe": recipe,
+            "details": details(&recipe),
+            "source": source,
+            "qr_svg": qr,
+        }),
+    ))
+}
+
+/// The recipe as a one page A5 PDF, served for `/recipes/{id}.pdf`.
+pub async fn pdf_recipe(
+    db: &DatabaseConnection,
+    household_id: i32,
+    recipe_id: i32,
+) -> error::Result<Response> {
+    let (recipe, video) = export::load_recipe(db, household_id, recipe_id).await?;
+    let source = recipe
+        .source_url
+        .as_deref()
+        .or(video.as_ref().and_then(uploads::source_url));
+
+    let pdf = render_pdf(&recipe, source)?;
+    let disposition = format!("inline; filename=\"{}.pdf\"", export::slug(&recipe));
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/pdf".to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        pdf,
+    )
+        .into_response())
+}
+
+/// A line of text on the card, `indent` in mm from the margin.
+struct Line {
+    text: String,
+    size: f32,
+    bold: bool,
+    indent: f32,
+    /// Extra space above the line, in mm.
+    gap: f32,
+}
+
+impl Line {
+    fn height(&self) -> f32 {
+        self.gap + self.size * 1.3 * PT
+    }
+}
+
+/// Split `text` into lines of at most `width` mm in a font of `size` points.
+fn wrap(text: &str, size: f32, width: f32) -> Vec<String> {
+    let max_chars = (width / (size * CHAR_WIDTH * PT)).floor().max(1.0) as usize;
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Ingredients then numbered steps at body text `size`, wrapped to `width` mm.
+fn body_lines(recipe: &recipes::Model, size: f32, width: f32) -> Vec<Line> {
+    let mut lines = vec![];
+    let heading = |text: &str, gap: f32| Line {
+        text: text.to_string(),
+        size: size + 2.0,
+        bold: true,
+        indent: 0.0,
+        gap,
+    };
+
+    // Hanging indents, so wrapped lines sit under the text rather than the bullet or number
+    let item = |marker: String, text: &str, indent: f32, lines: &mut Vec<Line>| {
+        for (i, text) in wrap(text, size, width - indent).into_iter().enumerate() {
+            let first = i == 0;
+            lines.push(Line {
+                text: if first {
+                    format!("{marker}{text}")
+                } else {
+                    text
+                },
+                size,
+                bold: false,
+                indent: if first { 0.0 } else { indent },
+                gap: if first { size * 0.25 * PT } else { 0.0 },
+            });
+        }
+    };
+
+    let ingredients = recipe.ingredients.as_deref().unwrap_or_default();
+    if !ingredients.is_empty() {
+        lines.push(heading("Ingredients", 0.0));
+        for ingredient in ingredients {
+            item(
+                "\u{2022} ".to_string(),
+                ingredient,
+                size * 0.8 * PT,
+                &mut lines,
+            );
+        }
+    }
+
+    let instructions = recipe.instructions.as_deref().unwrap_or_default();
+    if !instructions.is_empty() {
+        lines.push(heading("Method", size * PT));
+        for (i, step) in instructions.iter().enumerate() {
+            item(format!("{}. ", i + 1), step, size * 1.3 * PT, &mut lines);
+        }
+    }
+
+    lines
+}
+
+fn render_pdf(recipe: &recipes::Model, source: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let title = recipe.title.as_deref().unwrap_or("Untitled recipe");
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Card");
+    let layer = doc.get_page(page).get_layer(layer);
+
+    let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut top = PAGE_HEIGHT - MARGIN;
+
+    // Title and details beside the QR code, the body starts below whichever is lower
+    let header_width = if source.is_some() {
+        width - QR_SIZE - 5.0
+    } else {
+        width
+    };
+
+    let mut header = vec![];
+    for text in wrap(title, 18.0, header_width) {
+        header.push(Line {
+            text,
+            size: 18.0,
+            bold: true,
+            indent: 0.0,
+            gap: 0.0,
+        });
+    }
+
+    let details = details(recipe).join("  \u{b7}  ");
+    if !details.is_empty() {
+        header.push(Line {
+            text: details,
+            size: 10.0,
+            bold: false,
+            indent: 0.0,
+            gap: 1.5,
+        });
+    }
+
+    let header_bottom = draw(&layer, &header, top, &regular, &bold);
+
+    if let Some(source) = source {
+        draw_qr(&layer, source, &regular)?;
+        top = header_bottom.min(PAGE_HEIGHT - MARGIN - QR_SIZE - 5.0);
+    } else {
+        top = header_bottom;
+    }
+    top -= 6.0;
+
+    let available = top - MARGIN;
+    let mut body = vec![];
+
+    for &size in BODY_SIZES {
+        body = body_lines(recipe, size, width);
+        if body.iter().map(Line::height).sum::<f32>() <= available {
+            break;
+        }
+    }
+
+    // Still too long at the smallest size, keep what fits
+    let mut used = 0.0;
+    body.retain(|line| {
+        used += line.height();
+        used <= available
+    });
+
+    draw(&layer, &body, top, &regular, &bold);
+
+    doc.save_to_bytes().context("Failed to write the PDF")
+}
+
+/// Draw `lines` downwards from `top`, returning where they end.
+fn draw(
+    layer: &PdfLayerReference,
+    lines: &[Line],
+    mut top: f32,
+    regular: &IndirectFontRef,
+    bold: &IndirectFontRef,
+) -> f32 {
+    for line in lines {
+        top -= line.height();
+        let font = if line.bold { bold } else { regular };
+
+        // The baseline sits a little above the bottom of the line for descenders
+        layer.use_text(
+            line.text.as_str(),
+            line.size,
+            Mm(MARGIN + line.indent),
+            Mm(top + line.size * 0.3 * PT),
+            font,
+        );
+    }
+
+    top
+}
+
+/// The QR code linking to the reel in the top right corner, with a caption underneath.
+fn draw_qr(layer: &PdfLayerReference, url: &str, regular: &IndirectFontRef) -> anyhow::Result<()> {
+    let code = QrCode::new(url.as_bytes())?;
+    let modules = code.width();
+    let module_size = QR_SIZE / modules as f32;
+
+    let left = PAGE_WIDTH - MARGIN - QR_SIZE;
+    let top = PAGE_HEIGHT - MARGIN;
+
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == qrcode::Color::Dark {
+            let x = left + (i % modules) as f32 * module_size;
+            let y = top - (i / modules + 1) as f32 * module_size;
+
+            layer.add_rect(Rect::new(
+                Mm(x),
+                Mm(y),
+                Mm(x + module_size),
+                Mm(y + module_size),
+            ));
+        }
+    }
+
+    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.4, None)));
+    layer.use_text(
+        "Scan for the video",
+        7.0,
+        Mm(left + 2.5),
+        Mm(top - QR_SIZE - 3.5),
+        regular,
+    );
+    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::prelude::DateTimeWithTimeZone;
+
+    fn recipe(ingredients: &[&str], instructions: &[&str]) -> recipes::Model {
+        recipes::Model {
+            id: 1,
+            title: Some("Lemon pasta".to_string()),
+            ingredients: Some(ingredients.iter().map(|s| s.to_string()).collect()),
+            instructions: Some(instructions.iter().map(|s| s.to_string()).collect()),
+            updated_at: DateTimeWithTimeZone::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap(),
+            instagram_video_id: None,
+            generated_at: None,
+            owner_id: None,
+            household_id: Some(1),
+            servings: Some("4".to_string()),
+            prep_minutes: Some(10),
+            cook_minutes: Some(65),
+            notes: None,
+            source_url: None,
+            image: None,
+        }
+    }
+
+    #[test]
+    fn lists_the_details_it_has() {
+        assert_eq!(
+            details(&recipe(&[], &[])),
+            ["Serves 4", "Prep 10 min", "Cook 1 h 5 min"]
+        );
+
+        let mut bare = recipe(&[], &[]);
+        bare.servings = None;
+        bare.prep_minutes = None;
+        bare.cook_minutes = Some(120);
+        assert_eq!(details(&bare), ["Cook 2 h"]);
+    }
+
+    #[test]
+    fn wraps_on_words() {
+        // 10pt text fits 19 characters in 35mm
+        assert_eq!(
+            wrap("Boil the spaghetti in salted water", 10.0, 35.0),
+            ["Boil the spaghetti", "in salted water"]
+        );
+        assert_eq!(wrap("  ", 10.0, 35.0), Vec::<String>::new());
+        assert_eq!(
+            wrap("Supercalifragilistic", 10.0, 5.0),
+            ["Supercalifragilistic"]
+        );
+    }
+
+    #[test]
+    fn hangs_wrapped_items_under_their_text() {
+        let lines = body_lines(
+            &recipe(&["200g spaghetti"], &["Boil the spaghetti in salted water"]),
+            10.0,
+            40.0,
+        );
+        let texts: Vec<_> = lines.iter().map(|line| line.text.as_str()).collect();
+
+        assert_eq!(
+            texts,
+            [
+                "Ingredients",
+                "\u{2022} 200g spaghetti",
+                "Method",
+                "1. Boil the spaghetti",
+                "in salted water",
+            ]
+        );
+        assert!(lines[0].bold && lines[2].bold);
+        assert_eq!(lines[3].indent, 0.0);
+        assert!(lines[4].indent > 0.0);
+    }
+
+    #[test]
+    fn renders_a_one_page_pdf() {
+        let steps = vec!["Stir the sauce until it thickens and coats the back of a spoon"; 200];
+        let pdf = render_pdf(
+            &recipe(&["200g spaghetti", "1 lemon"], &steps),
+            Some("https://www.instagram.com/reel/ABC/"),
+        )
+        .unwrap();
+
+        assert!(pdf.starts_with(b"%PDF"));
+        assert!(pdf.trim_ascii_end().ends_with(b"%%EOF"));
+    }
+
+    #[test]
+    fn renders_the_qr_code_as_inline_svg() {
+        let svg = qr_svg("https://www.instagram.com/reel/ABC/").unwrap();
+        assert!(svg.starts_with("<svg"));
+    }
+}
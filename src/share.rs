@@ -0,0 +1,266 @@
+//! Installable PWA bits, so the app shows up in the phone's share sheet, and the share target
+//! itself which pulls a reel link out of whatever text the sharing app sends.
+
+use crate::auth::CurrentUser;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::error::{self, AppError};
+use crate::jobs;
+use crate::jobs::fetch_post::FetchJob;
+use crate::{negotiate, FangQueue};
+use anyhow::anyhow;
+use axum::extract::Query;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use axum_template::engine::Engine;
+use axum_template::RenderHtml;
+use lazy_static::lazy_static;
+use minijinja_autoreload::AutoReloader;
+use reqwest::Url;
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+
+lazy_static! {
+    static ref URL_REGEX: regex::Regex =
+        regex::Regex::new(r#"https?://[^\s<>"']+"#).expect("Failed to compile regex");
+}
+
+/// Query parameters added by share sheets and link shorteners that don't change what's linked.
+const TRACKING_PARAMS: &[&str] = &["igsh", "igshid", "fbclid", "gclid", "si", "ref"];
+
+fn is_tracking_param(name: &str) -> bool {
+    TRACKING_PARAMS.contains(&name) || name.starts_with("utm_")
+}
+
+/// Canonical form of a shared link: https, `www.instagram.com` rather than its mobile or bare
+/// variants, `/reel/` rather than `/reels/`, and no tracking parameters or fragment.
+pub fn normalise_url(raw: &str) -> Option<String> {
+    // Links at the end of a sentence pick up its punctuation
+    let raw = raw.trim_end_matches(['.', ',', ';', ':', '!', ')', ']']);
+    let mut url = Url::parse(raw).ok()?;
+
+    url.set_scheme("https").ok()?;
+    url.set_fragment(None);
+
+    if matches!(url.host_str(), Some("instagram.com" | "m.instagram.com")) {
+        url.set_host(Some("www.instagram.com")).ok()?;
+    }
+
+    if let Some(rest) = url.path().strip_prefix("/reels/") {
+        let path = format!("/reel/{rest}");
+        url.set_path(&path);
+    }
+
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+
+    if params.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+
+    Some(url.to_string())
+}
+
+/// Every link in `text`, normalised, in the order they appear.
+pub fn extract_urls(text: &str) -> impl Iterator<Item = String> + '_ {
+    URL_REGEX
+        .find_iter(text)
+        .filter_map(|m| normalise_url(m.as_str()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/manifest.webmanifest",
+    tag = "pwa",
+    security(()),
+    responses((status = 200, content_type = "application/manifest+json"))
+)]
+pub async fn manifest() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "application/manifest+json")],
+        include_str!("../app/pwa/manifest.webmanifest"),
+    )
+}
+
+/// Served from the root so its scope covers the whole app.
+#[utoipa::path(
+    get,
+    path = "/service-worker.js",
+    tag = "pwa",
+    security(()),
+    responses((status = 200, content_type = "text/javascript"))
+)]
+pub async fn service_worker() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/javascript")],
+        include_str!("../app/pwa/service-worker.js"),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/icon.svg",
+    tag = "pwa",
+    security(()),
+    responses((status = 200, content_type = "image/svg+xml"))
+)]
+pub async fn icon() -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "image/svg+xml")],
+        include_str!("../app/pwa/icon.svg"),
+    )
+}
+
+/// The fields named by `share_target.params` in the manifest, apps fill in whichever they like.
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+pub struct ShareRequest {
+    title: Option<String>,
+    text: Option<String>,
+    url: Option<String>,
+}
+
+/// The fetch for the first supported link in the shared content.
+fn find_job(request: &ShareRequest, user: &CurrentUser) -> error::Result<FetchJob> {
+    [&request.url, &request.text, &request.title]
+        .into_iter()
+        .flatten()
+        .flat_map(|field| extract_urls(field))
+        .find_map(|url| FetchJob::new(url, true, user.id, user.household_id).ok())
+        .ok_or_else(|| {
+            AppError::BadRequest(anyhow!("No Instagram reel or post link in what was shared"))
+        })
+}
+
+/// Confirm a share that arrived before signing in. The shared content comes back here through
+/// the login page's `next`, since the share itself was a POST the redirect can't repeat.
+#[utoipa::path(
+    get,
+    path = "/share",
+    tag = "recipes",
+    params(ShareRequest),
+    responses(
+        (status = 200, content_type = "text/html"),
+        (status = 400, description = "Nothing in the shared content is a supported link"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
+pub async fn confirm_share(
+    user: CurrentUser,
+    Extension(template_engine): Extension<Engine<AutoReloader>>,
+    Query(request): Query<ShareRequest>,
+) -> error::Result<impl IntoResponse> {
+    user.require(HouseholdRole::Editor)?;
+    let job = find_job(&request, &user)?;
+
+    Ok(RenderHtml(
+        "share/confirm.html",
+        template_engine,
+        json!({ "url": job.url(), "share": request }),
+    ))
+}
+
+/// Queue the first supported link found in the shared content, extracting its recipe straight away.
+/// Signed out, the content is kept in the login redirect and confirmed afterwards.
+#[utoipa::path(
+    post,
+    path = "/share",
+    tag = "recipes",
+    request_body(content_type = "application/x-www-form-urlencoded", content = inline(ShareRequest)),
+    responses(
+        (status = 303, description = "Queued, redirects to the recipe list, or to login when signed out"),
+        (status = 400, description = "Nothing in the shared content is a supported link"),
+        (status = 403, description = "Viewers can't add recipes"),
+    )
+)]
+pub async fn share(
+    user: Option<CurrentUser>,
+    headers: HeaderMap,
+    Extension(mut queue): Extension<FangQueue>,
+    Extension(db): Extension<DatabaseConnection>,
+    Form(request): Form<ShareRequest>,
+) -> error::Result<Response> {
+    let Some(user) = user else {
+        if negotiate::wants_json(&headers) {
+            return Err(AppError::Unauthorized);
+        }
+
+        let share = serde_urlencoded::to_string(&request).map_err(anyhow::Error::from)?;
+        let next = serde_urlencoded::to_string([("next", format!("/share?{share}"))])
+            .map_err(anyhow::Error::from)?;
+        return Ok(Redirect::to(&format!("/login?{next}")).into_response());
+    };
+
+    user.require(HouseholdRole::Editor)?;
+    let job = find_job(&request, &user)?;
+
+    tracing::info!("Queueing shared link {}", job.url());
+    jobs::enqueue(&mut queue, &db, job.job()).await?;
+
+    Ok(Redirect::to("/recipes?shared=true").into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_tracking_params() {
+        assert_eq!(
+            normalise_url("https://www.instagram.com/reel/C1a2b3/?igsh=abc&utm_source=ig_web&x=1")
+                .as_deref(),
+            Some("https://www.instagram.com/reel/C1a2b3/?x=1")
+        );
+        assert_eq!(
+            normalise_url("https://www.instagram.com/p/C1a2b3/?igshid=abc#comments").as_deref(),
+            Some("https://www.instagram.com/p/C1a2b3/")
+        );
+    }
+
+    #[test]
+    fn canonicalises_host_scheme_and_reels_path() {
+        assert_eq!(
+            normalise_url("http://m.instagram.com/reels/C1a2b3/").as_deref(),
+            Some("https://www.instagram.com/reel/C1a2b3/")
+        );
+        assert_eq!(
+            normalise_url("https://instagram.com/reels/C1a2b3").as_deref(),
+            Some("https://www.instagram.com/reel/C1a2b3")
+        );
+    }
+
+    #[test]
+    fn drops_trailing_punctuation() {
+        assert_eq!(
+            normalise_url("https://www.instagram.com/reel/C1a2b3/).").as_deref(),
+            Some("https://www.instagram.com/reel/C1a2b3/")
+        );
+    }
+
+    #[test]
+    fn rejects_non_urls() {
+        assert_eq!(normalise_url("not a link"), None);
+    }
+
+    #[test]
+    fn finds_every_link_in_order() {
+        let text = "Look at this! https://instagram.com/reels/AAA/?igsh=x, \
+                    and (https://www.instagram.com/p/BBB/). Via \"https://example.com/a\"";
+
+        assert_eq!(
+            extract_urls(text).collect::<Vec<_>>(),
+            [
+                "https://www.instagram.com/reel/AAA/",
+                "https://www.instagram.com/p/BBB/",
+                "https://example.com/a",
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::error;
+use crate::jobs::JobContext;
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::Resource;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::time::Instant;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Set up logging, and when an OTLP endpoint is given export spans to it as well.
+pub fn init_tracing(otlp_endpoint: Option<&str>) -> anyhow::Result<()> {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let otel_layer = match otlp_endpoint {
+        Some(endpoint) => {
+            let tracer =
+                opentelemetry_otlp::new_pipeline()
+                    .tracing()
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .tonic()
+                            .with_endpoint(endpoint),
+                    )
+                    .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                        Resource::new(vec![KeyValue::new("service.name", env!("CARGO_PKG_NAME"))]),
+                    ))
+                    .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    Ok(())
+}
+
+/// Flush any spans still buffered for export.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// W3C trace context carried inside job payloads so a job's spans join the trace that enqueued it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TraceContext(HashMap<String, String>);
+
+impl TraceContext {
+    /// Capture the context of the current span.
+    pub fn current() -> Self {
+        let mut carrier = HashMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&Span::current().context(), &mut carrier)
+        });
+
+        TraceContext(carrier)
+    }
+
+    /// Make the captured context the parent of `span`.
+    pub fn attach(&self, span: &Span) {
+        let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.extract(&self.0)
+        });
+        span.set_parent(parent);
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Span for each HTTP request, continuing the caller's trace if it sent a `traceparent` header.
+/// The `request_id` is the same one error responses report back to the client.
+pub fn make_http_span(request: &Request) -> Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| request.uri().path(), |path| path.as_str());
+
+    let request_id = request
+        .headers()
+        .get(error::REQUEST_ID_HEADER)
+        .and_then(|hv| hv.to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        route,
+        uri = %request.uri(),
+        request_id,
+    );
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Install the global Prometheus recorder, the returned handle renders the scrape output.
+pub fn install_recorder() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_string()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    Ok(handle)
+}
+
+/// Prometheus metrics in the text exposition format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "ops",
+    security(()),
+    responses((status = 200, content_type = "text/plain"))
+)]
+pub async fn metrics(
+    Extension(handle): Extension<PrometheusHandle>,
+    Extension(context): Extension<JobContext>,
+) -> impl IntoResponse {
+    let reel_dir = context.reel_dir.clone();
+
+    match tokio::task::spawn_blocking(move || reel_dir_usage(&reel_dir)).await {
+        Ok(Ok((files, bytes))) => {
+            gauge!("reel_dir_files").set(files as f64);
+            gauge!("reel_dir_bytes").set(bytes as f64);
+        }
+        Ok(Err(err)) => tracing::warn!("Failed to measure reel dir: {err:?}"),
+        Err(err) => tracing::warn!("Failed to measure reel dir: {err:?}"),
+    }
+
+    handle.render()
+}
+
+/// Files and bytes under `reel_dir`, including the folders of post images and imported photos.
+fn reel_dir_usage(reel_dir: &Path) -> std::io::Result<(u64, u64)> {
+    let mut files = 0;
+    let mut bytes = 0;
+    let mut dirs = vec![reel_dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            // Not following symlinks, so a link back up the tree can't loop forever
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files += 1;
+                bytes += entry.metadata()?.len();
+            }
+        }
+    }
+
+    Ok((files, bytes))
+}
+
+/// Middleware counting requests and their latency by route template rather than raw path.
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(start.elapsed().as_secs_f64());
+
+    response
+}
+
+/// Time one stage of the pipeline (download, audio extraction, transcription, completion).
+pub async fn time_stage<T>(stage: &'static str, future: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let output = future.await;
+    histogram!("pipeline_stage_duration_seconds", "stage" => stage)
+        .record(start.elapsed().as_secs_f64());
+
+    output
+}
+
+pub fn record_job_outcome(task_type: &'static str, outcome: &'static str) {
+    counter!("jobs_total", "task_type" => task_type, "outcome" => outcome).increment(1);
+}
+
+pub fn record_llm_tokens(prompt_tokens: u64, completion_tokens: u64) {
+    counter!("llm_tokens_total", "kind" => "prompt").increment(prompt_tokens);
+    counter!("llm_tokens_total", "kind" => "completion").increment(completion_tokens);
+}
@@ -0,0 +1,223 @@
+//! Videos uploaded straight from the device, for clips that never had an Instagram link, e.g.
+//! ones forwarded on WhatsApp. They go through the same transcription and extraction as reels.
+
+use crate::auth::CurrentUser;
+use crate::entities::instagram_video;
+use crate::entities::sea_orm_active_enums::HouseholdRole;
+use crate::error::{self, AppError};
+use crate::jobs::extract_thumbnail::ExtractThumbnailJob;
+use crate::jobs::extract_transcript::ExtractTranscriptJob;
+use crate::jobs::fetch_reel::ReelInfo;
+use crate::jobs::{self, JobContext};
+use crate::{negotiate, FangQueue};
+use anyhow::anyhow;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::Multipart;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Json};
+use sea_orm::prelude::Uuid;
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+use serde::Serialize;
+use std::path::Path;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+/// Room for the form's other fields on top of the video itself.
+pub const FORM_OVERHEAD_BYTES: usize = 64 * 1024;
+
+/// Start of the id uploads are stored under in place of an Instagram id.
+const UPLOAD_ID_PREFIX: &str = "upload-";
+
+/// The link to where `video` came from. Uploads have none, their `video_url` is only our own
+/// relative path to the file, which means nothing in an export or on paper.
+pub fn source_url(video: &instagram_video::Model) -> Option<&str> {
+    (!video.instagram_id.starts_with(UPLOAD_ID_PREFIX)).then_some(video.video_url.as_str())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UploadSettings {
+    pub max_video_bytes: u64,
+}
+
+impl UploadSettings {
+    fn limit_error(&self) -> AppError {
+        AppError::PayloadTooLarge {
+            limit_mb: self.max_video_bytes / (1024 * 1024),
+        }
+    }
+}
+
+/// MP4 and its relatives (MOV, M4V, 3GP) are ISO base media files, which open with an `ftyp` box.
+fn is_iso_media(head: &[u8]) -> bool {
+    head.get(4..8) == Some(b"ftyp")
+}
+
+fn multipart_error(settings: UploadSettings) -> impl Fn(MultipartError) -> AppError {
+    move |e| {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            settings.limit_error()
+        } else {
+            AppError::BadRequest(e.into())
+        }
+    }
+}
+
+/// Stream the uploaded video into a temporary file in `dir`, checking its size as it arrives and
+/// its type from the first bytes rather than whatever the browser claimed.
+async fn receive_video(
+    field: &mut Field<'_>,
+    dir: &Path,
+    settings: UploadSettings,
+) -> error::Result<NamedTempFile> {
+    let temp = tempfile::Builder::new()
+        .prefix(".upload-")
+        .tempfile_in(dir)
+        .map_err(anyhow::Error::from)?;
+
+    let mut file = tokio::fs::File::from_std(temp.reopen().map_err(anyhow::Error::from)?);
+    let mut head = Vec::with_capacity(12);
+    let mut written = 0u64;
+
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error(settings))? {
+        written += chunk.len() as u64;
+
+        if written > settings.max_video_bytes {
+            return Err(settings.limit_error());
+        }
+
+        // Give up on anything else as soon as we can tell
+        if head.len() < 12 {
+            head.extend(chunk.iter().take(12 - head.len()));
+
+            if head.len() == 12 && !is_iso_media(&head) {
+                break;
+            }
+        }
+
+        file.write_all(&chunk).await.map_err(anyhow::Error::from)?;
+    }
+
+    if written == 0 {
+        return Err(AppError::BadRequest(anyhow!("The uploaded video is empty")));
+    }
+
+    if !is_iso_media(&head) {
+        return Err(AppError::UnsupportedMediaType {
+            expected: "an MP4 or MOV video",
+        });
+    }
+
+    file.flush().await.map_err(anyhow::Error::from)?;
+
+    Ok(temp)
+}
+
+/// The fields of the upload form.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct VideoUpload {
+    /// MP4 or MOV file.
+    #[schema(value_type = String, format = Binary)]
+    video: Vec<u8>,
+    /// Stands in for the reel's caption, recipes are often only written out there.
+    description: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VideoUploaded {
+    video_id: i32,
+    /// The transcription job, recipe extraction is queued once it finishes.
+    job_id: String,
+}
+
+/// Upload a video to extract recipes from, as if it were a reel.
+#[utoipa::path(
+    post,
+    path = "/videos",
+    tag = "videos",
+    request_body(content_type = "multipart/form-data", content = inline(VideoUpload)),
+    responses(
+        (status = 202, description = "Stored and queued for transcription", body = VideoUploaded),
+        (status = 303, description = "Stored and queued, redirects to the recipe list"),
+        (status = 400, description = "No video in the form"),
+        (status = 403, description = "Viewers can't add recipes"),
+        (status = 413, description = "The video is over the size limit"),
+        (status = 415, description = "The file isn't an MP4 or MOV video"),
+    )
+)]
+pub async fn upload_video(
+    user: CurrentUser,
+    header_map: HeaderMap,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(context): Extension<JobContext>,
+    Extension(mut queue): Extension<FangQueue>,
+    Extension(settings): Extension<UploadSettings>,
+    mut multipart: Multipart,
+) -> error::Result<Response> {
+    user.require(HouseholdRole::Editor)?;
+
+    let mut description = String::new();
+    let mut upload = None;
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(multipart_error(settings))?
+    {
+        match field.name() {
+            Some("description") => {
+                description = field.text().await.map_err(multipart_error(settings))?;
+            }
+
+            Some("video") => {
+                upload = Some(receive_video(&mut field, &context.reel_dir, settings).await?);
+            }
+
+            _ => {}
+        }
+    }
+
+    let upload = upload.ok_or_else(|| AppError::BadRequest(anyhow!("No video was uploaded")))?;
+
+    // Stored alongside the reels, keyed the same way so `/videos/:id` serves it
+    let reel_id = format!("{UPLOAD_ID_PREFIX}{}", Uuid::new_v4().simple());
+    upload
+        .persist(context.video_path(&reel_id))
+        .map_err(anyhow::Error::from)?;
+
+    let video = instagram_video::ActiveModel {
+        video_url: Set(format!("/videos/{reel_id}")),
+        instagram_id: Set(reel_id),
+        info: Set(ReelInfo::from_description(description)),
+        owner_id: Set(Some(user.id)),
+
+        ..Default::default()
+    }
+    .insert(&db)
+    .await?;
+
+    tracing::info!("Stored uploaded video as video id: {}", video.id);
+
+    let job = ExtractTranscriptJob::new(video.id, &db)
+        .await?
+        .then_extract_recipes(user.id, user.household_id);
+    let task = jobs::enqueue(&mut queue, &db, &job).await?;
+
+    let job = ExtractThumbnailJob::new(video.id, video.instagram_id.clone());
+    jobs::enqueue(&mut queue, &db, &job).await?;
+
+    if negotiate::wants_json(&header_map) {
+        Ok((
+            StatusCode::ACCEPTED,
+            Json(VideoUploaded {
+                video_id: video.id,
+                job_id: task.id.to_string(),
+            }),
+        )
+            .into_response())
+    } else {
+        Ok(Redirect::to("/recipes?uploaded=true").into_response())
+    }
+}